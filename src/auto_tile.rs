@@ -0,0 +1,128 @@
+//! Picks the tiles of blocks from the blocks around them, so that the edges
+//! and corners of a tile set line up without choosing every tile by hand.
+//! Tile sets opt in with `AutoTileRules` in their `TileSetDefinition`.
+
+use bevy::{prelude::*, utils::HashSet};
+use serde::Deserialize;
+
+use crate::{
+    chunk_streaming::LoadedChunks,
+    coords,
+    tile_map::{SpawnBlockEvent, TileChangedEvent, TileMap, TileSetDefinitions},
+    GameState,
+};
+
+// Bits of a neighbour mask, set for each side of a tile that has a block
+pub const UP: u8 = 1;
+pub const DOWN: u8 = 2;
+pub const LEFT: u8 = 4;
+pub const RIGHT: u8 = 8;
+
+/// Bit of each side of a tile, with the offset from the tile to its neighbour there.
+const NEIGHBOURS: [(u8, [i32; 2]); 4] = [
+    (UP, [0, 1]),
+    (DOWN, [0, -1]),
+    (LEFT, [-1, 0]),
+    (RIGHT, [1, 0]),
+];
+
+fn neighbour_offsets() -> impl Iterator<Item = (u8, IVec2)> {
+    NEIGHBOURS
+        .iter()
+        .map(|(bit, offset)| (*bit, IVec2::from(*offset)))
+}
+
+/// Which tile of a tile set a block uses, depending on its neighbours.
+#[derive(Deserialize, Clone)]
+pub struct AutoTileRules {
+    /// Tile for every neighbour mask, indexed by the mask. The first four
+    /// are for blocks with no neighbours to the sides, then with a block
+    /// to the left, to the right and on both sides.
+    pub tiles: [usize; 16],
+    /// Tiles that fit a block with neighbours on all sides. Blocks made of
+    /// one of these are drawn with it when surrounded, so that different
+    /// fillings (like dirt and stone) survive being re-tiled.
+    #[serde(default)]
+    pub fill_tiles: Vec<usize>,
+}
+
+impl AutoTileRules {
+    /// Tile for a block with the given neighbours, made of `material`.
+    pub fn pick(&self, neighbours: u8, material: usize) -> usize {
+        if neighbours == UP | DOWN | LEFT | RIGHT && self.fill_tiles.contains(&material) {
+            material
+        } else {
+            self.tiles[neighbours as usize]
+        }
+    }
+}
+
+/// Mask of the sides of a tile at which `has_block` finds a block, given
+/// the offset from the tile to its neighbour.
+pub fn neighbour_mask(has_block: impl Fn(IVec2) -> bool) -> u8 {
+    neighbour_offsets()
+        .filter(|(_, offset)| has_block(*offset))
+        .fold(0, |mask, (bit, _)| mask | bit)
+}
+
+pub struct AutoTilePlugin;
+
+impl Plugin for AutoTilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_update(GameState::Game).with_system(auto_tile_system));
+    }
+}
+
+/// System that re-picks the tiles of changed blocks and walls and their
+/// neighbours. Blocks and walls are only tiled against their own layer.
+fn auto_tile_system(
+    definitions: Res<TileSetDefinitions>,
+    tile_map: Res<TileMap>,
+    loaded_chunks: Res<LoadedChunks>,
+    mut changed_events: EventReader<TileChangedEvent>,
+    mut spawn_events: EventWriter<SpawnBlockEvent>,
+) {
+    let mut tile_positions = HashSet::default();
+    for changed in changed_events.iter() {
+        tile_positions.insert((changed.layer, changed.tile_pos));
+        tile_positions.extend(
+            neighbour_offsets().map(|(_, offset)| (changed.layer, changed.tile_pos + offset)),
+        );
+    }
+
+    for (layer, tile_pos) in tile_positions {
+        let tile = match tile_map.get(layer, tile_pos) {
+            Some(tile) => tile,
+            None => continue,
+        };
+
+        let rules = match definitions
+            .get(&tile.tile_set)
+            .and_then(|definition| definition.auto_tile.as_ref())
+        {
+            Some(rules) => rules,
+            None => continue,
+        };
+
+        // Chunks that aren't loaded look empty, even though they might not be.
+        // The tile is picked again once they load.
+        let unknown_neighbours = neighbour_offsets()
+            .any(|(_, offset)| !loaded_chunks.contains(coords::tile_to_chunk(tile_pos + offset)));
+        if unknown_neighbours {
+            continue;
+        }
+
+        let neighbours = neighbour_mask(|offset| tile_map.contains(layer, tile_pos + offset));
+        let tile_index = rules.pick(neighbours, tile.material);
+
+        if tile_index != tile.tile_index {
+            spawn_events.send(SpawnBlockEvent {
+                layer,
+                tile_set: tile.tile_set.clone(),
+                tile_index,
+                material: tile.material,
+                tile_pos,
+            });
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Command line tool for inspecting and editing world saves. Run it from the
+//! game's directory so that it finds `world_saves` and `assets`.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    process::ExitCode,
+};
+
+use terons_crusade::{
+    save_data::{
+        inspect::{self, WorldDump},
+        slots, WorldMetadata,
+    },
+    world_gen,
+};
+
+const USAGE: &str = "Usage: terons-save <command>
+
+Commands:
+    list                     List every world with its metadata
+    create <world> [seed]    Generate a new world, from a random seed if
+                             none is given
+    dump <world> [file]      Write a world as JSON to a file, or to stdout
+    import <world> <file>    Replace a world with the contents of a JSON dump
+    validate <world>         Check a world for unknown tile sets, tiles and
+                             items, and for blocks sharing a tile
+    stats <world>            Count the blocks and items in a world";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args.as_slice() {
+        ["list"] => list(),
+        ["create", world_name] => create(world_name, None),
+        ["create", world_name, seed] => create(world_name, Some(seed)),
+        ["dump", world_name] => dump(world_name, None),
+        ["dump", world_name, path] => dump(world_name, Some(path)),
+        ["import", world_name, path] => import(world_name, path),
+        ["validate", world_name] => validate(world_name),
+        ["stats", world_name] => stats(world_name),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+type CommandResult = Result<ExitCode, Box<dyn std::error::Error>>;
+
+fn list() -> CommandResult {
+    for world_name in slots::list_worlds()? {
+        match slots::world_metadata(&world_name) {
+            Ok(metadata) => print_metadata(&metadata),
+            Err(e) => println!("{}: unreadable metadata ({})", world_name, e),
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn create(world_name: &str, seed: Option<&str>) -> CommandResult {
+    let seed = match seed {
+        Some(seed) => seed.parse()?,
+        None => world_gen::seed_from_args(),
+    };
+
+    slots::create_world(world_name, seed)?;
+    println!("Created world {} with seed {}", world_name, seed);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn dump(world_name: &str, path: Option<&str>) -> CommandResult {
+    let dump = inspect::read_world(world_name)?;
+
+    match path {
+        Some(path) => serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &dump)?,
+        None => serde_json::to_writer_pretty(io::stdout().lock(), &dump)?,
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn import(world_name: &str, path: &str) -> CommandResult {
+    let dump: WorldDump = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+    let problems = inspect::validate(&dump)?;
+    if !problems.is_empty() {
+        print_problems(&problems);
+        eprintln!("Not importing a world with problems");
+        return Ok(ExitCode::FAILURE);
+    }
+
+    inspect::write_world(world_name, dump)?;
+    println!("Imported {} into world {}", path, world_name);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn validate(world_name: &str) -> CommandResult {
+    let problems = inspect::validate(&inspect::read_world(world_name)?)?;
+
+    if problems.is_empty() {
+        println!("No problems found in world {}", world_name);
+        Ok(ExitCode::SUCCESS)
+    } else {
+        print_problems(&problems);
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+fn stats(world_name: &str) -> CommandResult {
+    let dump = inspect::read_world(world_name)?;
+    let stats = inspect::stats(&dump);
+
+    print_metadata(&dump.metadata);
+    println!("Chunks: {}", stats.chunk_count);
+
+    println!("Blocks: {}", dump.blocks.len());
+    for (tile_set, count) in &stats.blocks_per_tile_set {
+        println!("    {}: {}", tile_set, count);
+    }
+
+    println!("Walls: {}", dump.walls.len());
+    for (tile_set, count) in &stats.walls_per_tile_set {
+        println!("    {}: {}", tile_set, count);
+    }
+
+    println!("Liquids: {}", dump.liquids.len());
+    println!("    water: {}", stats.water_count);
+    println!("    lava: {}", stats.lava_count);
+
+    println!("Items: {}", dump.world_data.items.len());
+    for (item_name, count) in &stats.items_per_name {
+        println!("    {}: {}", item_name, count);
+    }
+
+    match &dump.world_data.player {
+        Some(player) => println!(
+            "Player: at ({}, {}), {} health, {}/{} inventory slots used",
+            player.position.x,
+            player.position.y,
+            player.health,
+            player.inventory_slots.iter().flatten().count(),
+            player.max_slots
+        ),
+        None => println!("Player: not saved yet"),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_metadata(metadata: &WorldMetadata) {
+    println!("{}", metadata.name);
+    println!("    Created:     {}", format_unix_time(metadata.created));
+    println!(
+        "    Last played: {}",
+        format_unix_time(metadata.last_played)
+    );
+    println!("    Playtime:    {}", format_duration(metadata.playtime));
+    println!("    Seed:        {}", metadata.seed);
+    println!("    Version:     {}", metadata.game_version);
+}
+
+fn print_problems(problems: &[String]) {
+    for problem in problems {
+        println!("{}", problem);
+    }
+    println!("{} problem(s) found", problems.len());
+}
+
+/// Formats seconds since the Unix epoch as a UTC date and time.
+fn format_unix_time(unix_time: u64) -> String {
+    let days = (unix_time / 86400) as i64;
+    let seconds_of_day = unix_time % 86400;
+
+    // Converts days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60
+    )
+}
+
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
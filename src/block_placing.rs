@@ -0,0 +1,85 @@
+//! Placing blocks and walls from `ItemType::Block` and `ItemType::Wall`
+//! items in the player's inventory.
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{Inventory, MainCamera, Player},
+    coords,
+    item::{ItemType, Items},
+    player,
+    tile_map::{SpawnBlockEvent, TileLayer, TileMap},
+    GameState,
+};
+
+pub struct BlockPlacingPlugin;
+
+impl Plugin for BlockPlacingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_update(GameState::Game).with_system(block_placing_system));
+    }
+}
+
+/// System that places the held block or wall item on the clicked tile, if
+/// the tile is empty, within reach and, for blocks, not in the way of the player.
+fn block_placing_system(
+    mouse: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    items: Res<Items>,
+    tile_map: Res<TileMap>,
+    mut spawn_events: EventWriter<SpawnBlockEvent>,
+    mut player_query: Query<(&Transform, &mut Inventory), With<Player>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let (player_tf, mut player_inv) = match player_query.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    let item_type = player_inv
+        .selected_item()
+        .and_then(|item_name| items.get(item_name))
+        .map(|item_data| &item_data.item_type);
+    let (layer, tile_set, tile_index) = match item_type {
+        Some(ItemType::Block {
+            tile_set,
+            tile_index,
+        }) => (TileLayer::Block, tile_set.clone(), *tile_index),
+        Some(ItemType::Wall {
+            tile_set,
+            tile_index,
+        }) => (TileLayer::Wall, tile_set.clone(), *tile_index),
+        _ => return,
+    };
+
+    let tile_pos = match camera_query
+        .get_single()
+        .ok()
+        .and_then(|(camera, camera_tf)| coords::cursor_tile(&windows, camera, camera_tf))
+    {
+        Some(tile_pos) => tile_pos,
+        None => return,
+    };
+
+    let player_pos = player_tf.translation.truncate();
+    // Walls can't get in the player's way, so they can be placed right behind them
+    if tile_map.contains(layer, tile_pos)
+        || !player::tile_in_reach(player_pos, tile_pos)
+        || (layer == TileLayer::Block && player::tile_overlaps_player(player_pos, tile_pos))
+    {
+        return;
+    }
+
+    player_inv.consume_selected();
+    spawn_events.send(SpawnBlockEvent {
+        layer,
+        tile_set,
+        tile_index,
+        material: tile_index,
+        tile_pos,
+    });
+}
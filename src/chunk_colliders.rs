@@ -0,0 +1,501 @@
+//! Gives every chunk a single fixed collider, made of its solid blocks merged
+//! into as few rectangles as possible. This is much cheaper for rapier than a
+//! collider per block, and leaves far fewer seams for bodies to catch on.
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    coords::{self, CHUNK_SIZE, TILE_SIZE},
+    tile_map::{TileChangedEvent, TileLayer, TileMap, TileSetDefinitions},
+    GameState,
+};
+
+/// A rectangle of tiles in a chunk.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TileRect {
+    /// Bottom left tile, relative to the chunk's bottom left tile.
+    pub min: IVec2,
+    /// Width and height, in tiles.
+    pub size: IVec2,
+}
+
+/// Collider entity of every chunk that has solid blocks.
+#[derive(Default)]
+pub struct ChunkColliders(HashMap<IVec2, Entity>);
+
+impl ChunkColliders {
+    /// Whether a chunk's collider has been spawned. Chunks without any
+    /// solid blocks never get one.
+    pub fn contains(&self, chunk_pos: IVec2) -> bool {
+        self.0.contains_key(&chunk_pos)
+    }
+}
+
+pub struct ChunkCollidersPlugin;
+
+impl Plugin for ChunkCollidersPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkColliders>().add_system_set(
+            SystemSet::on_update(GameState::Game).with_system(chunk_collider_system),
+        );
+    }
+}
+
+/// System that rebuilds the colliders of chunks whose blocks have changed.
+fn chunk_collider_system(
+    mut commands: Commands,
+    definitions: Res<TileSetDefinitions>,
+    tile_map: Res<TileMap>,
+    mut chunk_colliders: ResMut<ChunkColliders>,
+    mut changed_events: EventReader<TileChangedEvent>,
+) {
+    // Walls are only a backdrop, so changes to them can be ignored
+    let changed_chunks: HashSet<IVec2> = changed_events
+        .iter()
+        .filter(|changed| changed.layer == TileLayer::Block)
+        .map(|changed| coords::tile_to_chunk(changed.tile_pos))
+        .collect();
+
+    for chunk_pos in changed_chunks {
+        if let Some(collider_entity) = chunk_colliders.0.remove(&chunk_pos) {
+            commands.entity(collider_entity).despawn();
+        }
+
+        let first_tile = coords::chunk_to_tile(chunk_pos);
+        let rects = merge_tiles(|local_pos| {
+            tile_map
+                .get(TileLayer::Block, first_tile + local_pos)
+                .and_then(|tile| definitions.properties(tile))
+                .is_some_and(|properties| properties.solid)
+        });
+        if rects.is_empty() {
+            continue;
+        }
+
+        let collider_entity = commands
+            .spawn_bundle(TransformBundle::from_transform(
+                Transform::from_translation(coords::tile_to_world(first_tile).extend(0.)),
+            ))
+            .insert(RigidBody::Fixed)
+            .insert(chunk_collider(&rects))
+            .id();
+
+        chunk_colliders.0.insert(chunk_pos, collider_entity);
+    }
+}
+
+/// Builds a chunk's collider out of its merged rectangles, placed relative
+/// to the chunk's bottom left tile.
+///
+/// Where rectangles meet, along a floor or at the border between two chunks,
+/// they leave seams that a body could catch on. The bodies that move over
+/// the terrain, players and items, have rounded colliders that only get a
+/// tiny bump from them, so floors aren't rebuilt out of polylines instead.
+fn chunk_collider(rects: &[TileRect]) -> Collider {
+    let shapes = rects
+        .iter()
+        .map(|rect| {
+            // Tiles are centered on their position, so the center of a
+            // rectangle is half a tile less than half its size away
+            let center = (rect.min.as_vec2() + (rect.size.as_vec2() - Vec2::ONE) / 2.) * TILE_SIZE;
+            let half_extents = rect.size.as_vec2() * TILE_SIZE / 2.;
+
+            (center, 0., Collider::cuboid(half_extents.x, half_extents.y))
+        })
+        .collect();
+
+    Collider::compound(shapes)
+}
+
+/// Covers the tiles of a chunk for which `is_solid` is true with rectangles,
+/// given the tile's position relative to the chunk. Tiles are merged along
+/// rows first, so that floors end up as few and as wide rectangles as possible.
+pub fn merge_tiles(is_solid: impl Fn(IVec2) -> bool) -> Vec<TileRect> {
+    const SIZE: usize = CHUNK_SIZE as usize;
+
+    let mut solid = [[false; SIZE]; SIZE];
+    for (y, row) in solid.iter_mut().enumerate() {
+        for (x, tile) in row.iter_mut().enumerate() {
+            *tile = is_solid(IVec2::new(x as i32, y as i32));
+        }
+    }
+
+    let mut rects = Vec::new();
+
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            if !solid[y][x] {
+                continue;
+            }
+
+            let mut width = 1;
+            while x + width < SIZE && solid[y][x + width] {
+                width += 1;
+            }
+
+            let mut height = 1;
+            while y + height < SIZE && solid[y + height][x..x + width].iter().all(|tile| *tile) {
+                height += 1;
+            }
+
+            // Covered tiles don't need to be merged again
+            for row in &mut solid[y..y + height] {
+                row[x..x + width].fill(false);
+            }
+
+            rects.push(TileRect {
+                min: IVec2::new(x as i32, y as i32),
+                size: IVec2::new(width as i32, height as i32),
+            });
+        }
+    }
+
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use bevy::transform::TransformPlugin;
+
+    use super::*;
+    use crate::{
+        player::{PLAYER_HALF_HEIGHT, PLAYER_RADIUS},
+        tile_map,
+        world_gen::{WorldGenerator, TERRAIN_TILE_SET, WORLD_CHUNKS_Y},
+        SPRITE_SCALE,
+    };
+
+    /// Merges the tiles of a chunk drawn as rows of text from the top row
+    /// down, with `#` for solid tiles.
+    fn merge_drawing(rows: &[&str]) -> (HashSet<IVec2>, Vec<TileRect>) {
+        let solid_tiles: HashSet<IVec2> = rows
+            .iter()
+            .rev()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.chars()
+                    .enumerate()
+                    .filter(|(_, tile)| *tile == '#')
+                    .map(move |(x, _)| IVec2::new(x as i32, y as i32))
+            })
+            .collect();
+
+        let rects = merge_tiles(|local_pos| solid_tiles.contains(&local_pos));
+        (solid_tiles, rects)
+    }
+
+    /// Checks that the rectangles cover every solid tile exactly once, and nothing else.
+    fn assert_covers(solid_tiles: &HashSet<IVec2>, rects: &[TileRect]) {
+        let mut covered = HashSet::default();
+        for rect in rects {
+            for y in rect.min.y..rect.min.y + rect.size.y {
+                for x in rect.min.x..rect.min.x + rect.size.x {
+                    assert!(
+                        covered.insert(IVec2::new(x, y)),
+                        "{:?} is covered twice",
+                        (x, y)
+                    );
+                }
+            }
+        }
+
+        assert_eq!(&covered, solid_tiles);
+    }
+
+    #[test]
+    fn empty_chunk_has_no_rects() {
+        assert!(merge_tiles(|_| false).is_empty());
+    }
+
+    #[test]
+    fn full_chunk_is_one_rect() {
+        assert_eq!(
+            merge_tiles(|_| true),
+            [TileRect {
+                min: IVec2::ZERO,
+                size: IVec2::splat(CHUNK_SIZE),
+            }]
+        );
+    }
+
+    #[test]
+    fn single_tile() {
+        let (solid_tiles, rects) = merge_drawing(&["", " #", ""]);
+
+        assert_eq!(
+            rects,
+            [TileRect {
+                min: IVec2::new(1, 1),
+                size: IVec2::ONE,
+            }]
+        );
+        assert_covers(&solid_tiles, &rects);
+    }
+
+    #[test]
+    fn l_shape() {
+        let (solid_tiles, rects) = merge_drawing(&["#", "#", "#", "####"]);
+
+        // The floor is merged first, then the wall on top of it
+        assert_eq!(
+            rects,
+            [
+                TileRect {
+                    min: IVec2::new(0, 0),
+                    size: IVec2::new(4, 1),
+                },
+                TileRect {
+                    min: IVec2::new(0, 1),
+                    size: IVec2::new(1, 3),
+                },
+            ]
+        );
+        assert_covers(&solid_tiles, &rects);
+    }
+
+    #[test]
+    fn hole() {
+        let (solid_tiles, rects) = merge_drawing(&["####", "#  #", "#  #", "####"]);
+
+        assert_eq!(rects.len(), 4);
+        assert!(!rects.iter().any(|rect| rect.min == IVec2::new(1, 1)));
+        assert_covers(&solid_tiles, &rects);
+    }
+
+    #[test]
+    fn chunk_edges() {
+        let full_row = "#".repeat(CHUNK_SIZE as usize);
+        let mut rows = vec![full_row.as_str()];
+        rows.extend(["#"; CHUNK_SIZE as usize - 2]);
+        rows.push(&full_row);
+        let (solid_tiles, rects) = merge_drawing(&rows);
+
+        assert_eq!(rects.len(), 3);
+        assert_covers(&solid_tiles, &rects);
+    }
+
+    /// Positions of the solid tiles in every chunk of a generated world,
+    /// relative to their chunk.
+    fn generated_chunks() -> HashMap<IVec2, HashSet<IVec2>> {
+        let definitions = tile_map::load_tile_set_definitions().unwrap();
+        let terrain_rules = definitions
+            .get(TERRAIN_TILE_SET)
+            .and_then(|definition| definition.auto_tile.clone())
+            .unwrap();
+
+        // Every generated block is solid
+        let mut chunks = HashMap::<IVec2, HashSet<IVec2>>::default();
+        for generated in WorldGenerator::new(42, terrain_rules).generate() {
+            if generated.layer == TileLayer::Block {
+                chunks
+                    .entry(coords::tile_to_chunk(generated.tile_pos))
+                    .or_default()
+                    .insert(coords::tile_to_local(generated.tile_pos));
+            }
+        }
+
+        chunks
+    }
+
+    /// An app that only steps physics, with the game's scale and gravity,
+    /// by a fixed amount every update.
+    fn physics_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(TransformPlugin)
+            .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(
+                SPRITE_SCALE,
+            ))
+            .insert_resource(RapierConfiguration {
+                gravity: Vec2::new(0., -1500.),
+                timestep_mode: TimestepMode::Fixed {
+                    dt: 1. / 60.,
+                    substeps: 1,
+                },
+                ..Default::default()
+            });
+        app
+    }
+
+    /// Spawns the colliders of the given chunks, either merged like the
+    /// game does or as a collider per block like it used to.
+    fn spawn_terrain(app: &mut App, chunks: &HashMap<IVec2, HashSet<IVec2>>, merged: bool) {
+        for (chunk_pos, solid_tiles) in chunks {
+            let first_tile = coords::chunk_to_tile(*chunk_pos);
+
+            if merged {
+                let rects = merge_tiles(|local_pos| solid_tiles.contains(&local_pos));
+                app.world
+                    .spawn()
+                    .insert_bundle(TransformBundle::from_transform(
+                        Transform::from_translation(coords::tile_to_world(first_tile).extend(0.)),
+                    ))
+                    .insert(RigidBody::Fixed)
+                    .insert(chunk_collider(&rects));
+            } else {
+                for local_pos in solid_tiles {
+                    let tile_pos = first_tile + *local_pos;
+                    app.world
+                        .spawn()
+                        .insert_bundle(TransformBundle::from_transform(
+                            Transform::from_translation(coords::tile_to_world(tile_pos).extend(0.)),
+                        ))
+                        .insert(RigidBody::Fixed)
+                        .insert(Collider::cuboid(TILE_SIZE / 2., TILE_SIZE / 2.));
+                }
+            }
+        }
+    }
+
+    /// Merging leaves a generated world with a fraction of its colliders.
+    #[test]
+    fn merging_generated_terrain() {
+        let chunks = generated_chunks();
+
+        let block_colliders: usize = chunks.values().map(HashSet::len).sum();
+        let mut merged_colliders = 0;
+        for solid_tiles in chunks.values() {
+            let rects = merge_tiles(|local_pos| solid_tiles.contains(&local_pos));
+            assert_covers(solid_tiles, &rects);
+            merged_colliders += rects.len();
+        }
+
+        assert!(merged_colliders * 10 < block_colliders);
+    }
+
+    /// Walks the player's collider along a floor with seams between its
+    /// rectangles every few tiles and at two chunk borders, checking that
+    /// it's never slowed down or bumped up by them.
+    #[test]
+    fn player_slides_over_seams() {
+        // Gaps below the floor split it into separate rectangles
+        let floor: HashSet<IVec2> = (0..CHUNK_SIZE)
+            .flat_map(|x| [IVec2::new(x, 0), IVec2::new(x, 1)])
+            .filter(|tile_pos| tile_pos.y == 1 || tile_pos.x % 4 != 1)
+            .collect();
+        assert!(merge_tiles(|local_pos| floor.contains(&local_pos)).len() > 4);
+
+        let chunks = (0..3)
+            .map(|chunk_x| (IVec2::new(chunk_x, 0), floor.clone()))
+            .collect();
+        let mut app = physics_app();
+        spawn_terrain(&mut app, &chunks, true);
+
+        let floor_top = coords::tile_to_world(IVec2::new(0, 1)).y + TILE_SIZE / 2.;
+        let player = app
+            .world
+            .spawn()
+            .insert_bundle(TransformBundle::from_transform(Transform {
+                translation: Vec3::new(
+                    TILE_SIZE,
+                    floor_top + (PLAYER_HALF_HEIGHT + PLAYER_RADIUS) * SPRITE_SCALE,
+                    0.,
+                ),
+                scale: Vec3::new(SPRITE_SCALE, SPRITE_SCALE, 1.),
+                ..Default::default()
+            }))
+            .insert(RigidBody::Dynamic)
+            .insert(Collider::capsule_y(PLAYER_HALF_HEIGHT, PLAYER_RADIUS))
+            .insert(LockedAxes::ROTATION_LOCKED)
+            .insert(Velocity::default())
+            .id();
+
+        // Let the player settle on the floor first
+        for _ in 0..30 {
+            app.update();
+        }
+
+        let rest_y = app.world.get::<Transform>(player).unwrap().translation.y;
+        let end_x = coords::tile_to_world(IVec2::new(CHUNK_SIZE * 2 + 2, 0)).x;
+        let speed = 170.;
+        let mut frames = 0;
+
+        while app.world.get::<Transform>(player).unwrap().translation.x < end_x {
+            app.world.get_mut::<Velocity>(player).unwrap().linvel = Vec2::new(speed, 0.);
+            app.update();
+
+            // Friction takes some of the speed every step, seams only a little more
+            let linvel = app.world.get::<Velocity>(player).unwrap().linvel;
+            let translation = app.world.get::<Transform>(player).unwrap().translation;
+            let x = translation.x;
+            assert!(
+                linvel.x > speed * 0.8,
+                "slowed down to {} at {}",
+                linvel.x,
+                x
+            );
+            assert!(
+                translation.y - rest_y < 1.,
+                "bumped up to {} at {}",
+                translation.y,
+                x
+            );
+
+            frames += 1;
+            assert!(frames < 600, "stuck at {}", x);
+        }
+    }
+
+    /// Compares how long physics takes to step with a generated world's
+    /// blocks merged into a collider per chunk, and with a collider per
+    /// block, as bodies fall onto the terrain and come to rest on it.
+    /// Run with `cargo test time_generated_terrain_physics -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn time_generated_terrain_physics() {
+        const STEPS: u32 = 300;
+
+        let chunks = generated_chunks();
+        let world_top = coords::chunk_to_tile(IVec2::new(0, WORLD_CHUNKS_Y.end)).y;
+
+        for merged in [false, true] {
+            let mut app = physics_app();
+            spawn_terrain(&mut app, &chunks, merged);
+
+            // Drop a body above every fourth column
+            let columns = chunks
+                .keys()
+                .map(|chunk_pos| coords::chunk_to_tile(*chunk_pos).x);
+            let (min_x, max_x) = (columns.clone().min().unwrap(), columns.max().unwrap());
+            for x in (min_x..max_x + CHUNK_SIZE).step_by(4) {
+                app.world
+                    .spawn()
+                    .insert_bundle(TransformBundle::from_transform(
+                        Transform::from_translation(
+                            coords::tile_to_world(IVec2::new(x, world_top)).extend(0.),
+                        ),
+                    ))
+                    .insert(RigidBody::Dynamic)
+                    .insert(Collider::round_cuboid(4., 4., 2.));
+            }
+
+            let start = Instant::now();
+            app.update();
+            let setup = start.elapsed();
+
+            let start = Instant::now();
+            for _ in 0..STEPS {
+                app.update();
+            }
+            let stepping = start.elapsed();
+
+            println!(
+                "{}: {} terrain colliders, first step {:?}, then {:?} per step",
+                if merged { "merged" } else { "per block" },
+                app.world
+                    .query::<&RigidBody>()
+                    .iter(&app.world)
+                    .filter(|body| **body == RigidBody::Fixed)
+                    .count(),
+                setup,
+                stepping / STEPS,
+            );
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Draws the blocks and walls of each chunk as one mesh per layer and tile
+//! set, instead of a sprite entity per tile, so that huge numbers of tiles
+//! can be drawn. Tiles are grouped by light level as well, so that each
+//! mesh can be tinted with a single material. Meshes are rebuilt on the
+//! CPU whenever a tile or the light in their chunk changes.
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology},
+    sprite::{MaterialMesh2dBundle, Rect},
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    coords::{self, TILE_SIZE},
+    lighting::{ChunkLightChangedEvent, LightMap},
+    tile_map::{TileChangedEvent, TileLayer, TileMap, TileSets},
+    GameState,
+};
+
+/// Depth of wall meshes, behind the blocks at a depth of 0.
+const WALL_Z: f32 = -1.;
+
+/// Vertices of the quads of a chunk mesh, before they're turned into a `Mesh`.
+#[derive(Default, Debug)]
+pub struct ChunkMeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+impl ChunkMeshData {
+    /// Builds the quads of one tile set's tiles in a chunk, given as their
+    /// position relative to the chunk's bottom left tile and their tile index.
+    /// Tiles that the atlas doesn't have are skipped.
+    pub fn build(tiles: impl IntoIterator<Item = (IVec2, usize)>, atlas: &TextureAtlas) -> Self {
+        let mut mesh_data = Self::default();
+
+        for (local_pos, tile_index) in tiles {
+            if let Some(rect) = atlas.textures.get(tile_index) {
+                mesh_data.push_quad(local_pos, rect, atlas.size);
+            }
+        }
+
+        mesh_data
+    }
+
+    /// Builds untextured quads that fill tiles from the bottom up, given as
+    /// their position relative to the chunk's bottom left tile and how full
+    /// they are from 0 to 1.
+    pub fn build_filled(tiles: impl IntoIterator<Item = (IVec2, f32)>) -> Self {
+        let mut mesh_data = Self::default();
+
+        for (local_pos, fill) in tiles {
+            let bottom = local_pos.as_vec2() * TILE_SIZE - Vec2::splat(TILE_SIZE / 2.);
+            let size = Vec2::new(TILE_SIZE, TILE_SIZE * fill.clamp(0., 1.));
+            mesh_data.push_rect(bottom, bottom + size, [Vec2::ZERO; 4]);
+        }
+
+        mesh_data
+    }
+
+    /// Adds a tile sized quad centered on a tile, textured with
+    /// the given part of an atlas of size `atlas_size`.
+    fn push_quad(&mut self, local_pos: IVec2, rect: &Rect, atlas_size: Vec2) {
+        let center = local_pos.as_vec2() * TILE_SIZE;
+        let half_size = Vec2::splat(TILE_SIZE / 2.);
+
+        // Image coordinates go down, so the top of the tile is the top of the rect
+        let uvs = [
+            Vec2::new(rect.min.x, rect.max.y),
+            Vec2::new(rect.max.x, rect.max.y),
+            Vec2::new(rect.max.x, rect.min.y),
+            Vec2::new(rect.min.x, rect.min.y),
+        ]
+        .map(|uv| uv / atlas_size);
+
+        self.push_rect(center - half_size, center + half_size, uvs);
+    }
+
+    /// Adds a quad covering a rectangle, with the UVs of its corners
+    /// counter-clockwise from the bottom left corner.
+    fn push_rect(&mut self, min: Vec2, max: Vec2, uvs: [Vec2; 4]) {
+        let first_vertex = self.positions.len() as u32;
+        let corners = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+
+        for (corner, uv) in corners.into_iter().zip(uvs) {
+            self.positions.push(corner.extend(0.).to_array());
+            self.uvs.push(uv.to_array());
+        }
+
+        self.indices
+            .extend([0, 1, 2, 0, 2, 3].iter().map(|index| first_vertex + index));
+    }
+
+    pub fn into_mesh(self) -> Mesh {
+        let normals = vec![[0., 0., 1.]; self.positions.len()];
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+        mesh
+    }
+}
+
+/// Mesh entities of every chunk that has tiles, one per layer, tile set
+/// and light level in the chunk.
+#[derive(Default)]
+struct ChunkMeshes(HashMap<IVec2, Vec<Entity>>);
+
+pub struct ChunkMeshPlugin;
+
+impl Plugin for ChunkMeshPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkMeshes>()
+            .add_system_set(SystemSet::on_update(GameState::Game).with_system(chunk_mesh_system));
+    }
+}
+
+/// The tiles and light that chunk meshes are built from, and the events
+/// sent when they change.
+#[derive(SystemParam)]
+struct ChunkMeshSources<'w, 's> {
+    tile_map: Res<'w, TileMap>,
+    light_map: Res<'w, LightMap>,
+    changed_events: EventReader<'w, 's, TileChangedEvent>,
+    light_events: EventReader<'w, 's, ChunkLightChangedEvent>,
+}
+
+impl ChunkMeshSources<'_, '_> {
+    /// Chunks whose tiles or light have changed since the last call.
+    fn changed_chunks(&mut self) -> HashSet<IVec2> {
+        let mut changed_chunks: HashSet<IVec2> = self
+            .changed_events
+            .iter()
+            .map(|changed| coords::tile_to_chunk(changed.tile_pos))
+            .collect();
+        changed_chunks.extend(self.light_events.iter().map(|changed| changed.chunk_pos));
+        changed_chunks
+    }
+}
+
+/// System that rebuilds the meshes of chunks whose tiles or light have changed.
+fn chunk_mesh_system(
+    mut commands: Commands,
+    mut sources: ChunkMeshSources,
+    tile_sets: Res<TileSets>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut chunk_meshes: ResMut<ChunkMeshes>,
+) {
+    for chunk_pos in sources.changed_chunks() {
+        for mesh_entity in chunk_meshes.0.remove(&chunk_pos).into_iter().flatten() {
+            commands.entity(mesh_entity).despawn();
+        }
+
+        let first_tile = coords::chunk_to_tile(chunk_pos);
+        let mut mesh_entities = Vec::new();
+
+        for layer in TileLayer::ALL {
+            let mut tiles_by_set = HashMap::<(&str, u8), Vec<(IVec2, usize)>>::default();
+            for tile_pos in coords::chunk_tiles(chunk_pos) {
+                if let Some(tile) = sources.tile_map.get(layer, tile_pos) {
+                    tiles_by_set
+                        .entry((&tile.tile_set, sources.light_map.level(tile_pos)))
+                        .or_default()
+                        .push((tile_pos - first_tile, tile.tile_index));
+                }
+            }
+
+            for ((tile_set, light_level), tiles) in tiles_by_set {
+                let (tile_set_assets, atlas) = match tile_sets
+                    .get(tile_set)
+                    .and_then(|assets| Some((assets, texture_atlases.get(&assets.atlas)?)))
+                {
+                    Some(tile_set) => tile_set,
+                    None => continue,
+                };
+
+                let z = match layer {
+                    TileLayer::Block => 0.,
+                    TileLayer::Wall => WALL_Z,
+                };
+                let mesh = ChunkMeshData::build(tiles, atlas).into_mesh();
+
+                mesh_entities.push(
+                    commands
+                        .spawn_bundle(MaterialMesh2dBundle {
+                            mesh: meshes.add(mesh).into(),
+                            material: tile_set_assets.material(layer, light_level).clone(),
+                            transform: Transform::from_translation(
+                                coords::tile_to_world(first_tile).extend(z),
+                            ),
+                            ..Default::default()
+                        })
+                        .id(),
+                );
+            }
+        }
+
+        if !mesh_entities.is_empty() {
+            chunk_meshes.0.insert(chunk_pos, mesh_entities);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coords::CHUNK_SIZE;
+
+    const TILE_COUNT: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+    /// Atlas of 5 by 5 tiles of 16 pixels, like the jungle floor sheet.
+    fn atlas() -> TextureAtlas {
+        TextureAtlas::from_grid(Handle::default(), Vec2::splat(16.), 5, 5)
+    }
+
+    fn local_tiles() -> impl Iterator<Item = IVec2> {
+        coords::chunk_tiles(IVec2::ZERO)
+    }
+
+    fn assert_quad_counts(mesh_data: &ChunkMeshData, quads: usize) {
+        assert_eq!(mesh_data.positions.len(), quads * 4);
+        assert_eq!(mesh_data.uvs.len(), quads * 4);
+        assert_eq!(mesh_data.indices.len(), quads * 6);
+        assert!(mesh_data
+            .indices
+            .iter()
+            .all(|index| (*index as usize) < mesh_data.positions.len()));
+    }
+
+    #[test]
+    fn empty_chunk() {
+        assert_quad_counts(&ChunkMeshData::build([], &atlas()), 0);
+        assert_quad_counts(&ChunkMeshData::build_filled([]), 0);
+    }
+
+    #[test]
+    fn full_chunk() {
+        let mesh_data =
+            ChunkMeshData::build(local_tiles().map(|local_pos| (local_pos, 7)), &atlas());
+        assert_quad_counts(&mesh_data, TILE_COUNT);
+
+        // Tile 7 is the third tile of the second row of the sheet
+        for uv in &mesh_data.uvs {
+            assert!((0.4..=0.6).contains(&uv[0]), "{:?}", uv);
+            assert!((0.2..=0.4).contains(&uv[1]), "{:?}", uv);
+        }
+
+        let filled = ChunkMeshData::build_filled(local_tiles().map(|local_pos| (local_pos, 1.)));
+        assert_quad_counts(&filled, TILE_COUNT);
+    }
+
+    #[test]
+    fn mixed_tile_set_chunk() {
+        // Every tile of the sheet, plus tiles that it doesn't have
+        let tiles = local_tiles().zip((0..30).cycle());
+        let mesh_data = ChunkMeshData::build(tiles, &atlas());
+
+        let missing_tiles = local_tiles()
+            .zip((0..30).cycle())
+            .filter(|(_, tile_index)| *tile_index >= 25)
+            .count();
+        assert_quad_counts(&mesh_data, TILE_COUNT - missing_tiles);
+
+        // The first quad is tile 0, in the top left corner of the sheet,
+        // with its bottom left corner at the bottom left of the tile
+        assert_eq!(
+            mesh_data.positions[0],
+            [-TILE_SIZE / 2., -TILE_SIZE / 2., 0.]
+        );
+        assert_eq!(
+            &mesh_data.uvs[..4],
+            [[0., 0.2], [0.2, 0.2], [0.2, 0.], [0., 0.]]
+        );
+    }
+
+    #[test]
+    fn filled_tiles_grow_from_the_bottom() {
+        let mesh_data = ChunkMeshData::build_filled([(IVec2::ZERO, 0.25), (IVec2::new(1, 0), 2.)]);
+        assert_quad_counts(&mesh_data, 2);
+
+        let bottom = -TILE_SIZE / 2.;
+        assert_eq!(mesh_data.positions[0][1], bottom);
+        assert_eq!(mesh_data.positions[2][1], bottom + TILE_SIZE * 0.25);
+        // Fills are clamped to a full tile
+        assert_eq!(mesh_data.positions[6][1], bottom + TILE_SIZE);
+    }
+}
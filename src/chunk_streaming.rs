@@ -0,0 +1,202 @@
+//! Keeps only the chunks around the camera spawned. Chunks are read from the
+//! `WorldStore` as the camera comes near them, and their tiles are despawned
+//! again once it has moved far enough away. Items in chunks that aren't
+//! loaded, or whose collider hasn't been built yet, are frozen in place, so
+//! that they don't fall out of the world.
+
+use bevy::{prelude::*, utils::HashSet};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    chunk_colliders::ChunkColliders,
+    components::{Frozen, Item, MainCamera},
+    coords,
+    save_data::WorldStore,
+    tile_map::{
+        BlockSpawnLabel, DespawnBlockEvent, SpawnBlockEvent, TileChangedEvent, TileLayer, TileMap,
+        TileSetDefinitions,
+    },
+    GameState,
+};
+
+/// Chunks this many chunks away from the camera's chunk get loaded.
+const LOAD_RADIUS: i32 = 2;
+/// Chunks further than this many chunks away from the camera's chunk get
+/// unloaded. Larger than `LOAD_RADIUS`, so that moving back and forth
+/// over a chunk border doesn't keep reloading the same chunks.
+const UNLOAD_RADIUS: i32 = 3;
+
+/// Chunks whose blocks and walls are currently spawned.
+#[derive(Default)]
+pub struct LoadedChunks(HashSet<IVec2>);
+
+impl LoadedChunks {
+    pub fn contains(&self, chunk_pos: IVec2) -> bool {
+        self.0.contains(&chunk_pos)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+pub struct ChunkStreamingPlugin;
+
+impl Plugin for ChunkStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoadedChunks>().add_system_set(
+            SystemSet::on_update(GameState::Game)
+                // A chunk's blocks are spawned in the same frame that it's loaded
+                .with_system(chunk_streaming_system.before(BlockSpawnLabel))
+                .with_system(item_freeze_system),
+        );
+    }
+}
+
+/// System that copies tile changes in loaded chunks into the `WorldStore`,
+/// spawns the tiles of chunks near the camera and despawns far away ones.
+fn chunk_streaming_system(
+    world_store: Option<ResMut<WorldStore>>,
+    tile_map: Res<TileMap>,
+    mut loaded_chunks: ResMut<LoadedChunks>,
+    mut changed_events: EventReader<TileChangedEvent>,
+    mut spawn_events: EventWriter<SpawnBlockEvent>,
+    mut despawn_events: EventWriter<DespawnBlockEvent>,
+    camera_query: Query<&Transform, With<MainCamera>>,
+) {
+    // No world has been loaded yet
+    let mut world_store = match world_store {
+        Some(world_store) => world_store,
+        None => return,
+    };
+
+    for changed in changed_events.iter() {
+        if loaded_chunks.contains(coords::tile_to_chunk(changed.tile_pos)) {
+            store_tile(&mut world_store, &tile_map, changed.layer, changed.tile_pos);
+        }
+    }
+
+    let camera_chunk = match camera_query.get_single() {
+        Ok(camera_tf) => coords::world_to_chunk(camera_tf.translation.truncate()),
+        Err(_) => return,
+    };
+    let distance = |chunk_pos: IVec2| (chunk_pos - camera_chunk).abs().max_element();
+
+    // Unload far away chunks
+    let far_chunks: Vec<IVec2> = loaded_chunks
+        .0
+        .iter()
+        .copied()
+        .filter(|chunk_pos| distance(*chunk_pos) > UNLOAD_RADIUS)
+        .collect();
+
+    for chunk_pos in far_chunks {
+        // Removed before despawning, so that the tiles despawning
+        // isn't mistaken for them being removed from the world
+        loaded_chunks.0.remove(&chunk_pos);
+
+        for tile_pos in coords::chunk_tiles(chunk_pos) {
+            for layer in TileLayer::ALL {
+                // Changes made this frame haven't been seen as events yet
+                store_tile(&mut world_store, &tile_map, layer, tile_pos);
+
+                if tile_map.contains(layer, tile_pos) {
+                    despawn_events.send(DespawnBlockEvent { layer, tile_pos });
+                }
+            }
+        }
+    }
+
+    // Load nearby chunks
+    for chunk_y in -LOAD_RADIUS..=LOAD_RADIUS {
+        for chunk_x in -LOAD_RADIUS..=LOAD_RADIUS {
+            let chunk_pos = camera_chunk + IVec2::new(chunk_x, chunk_y);
+
+            if !loaded_chunks.0.insert(chunk_pos) {
+                continue;
+            }
+
+            if let Some(chunk) = world_store.chunk(chunk_pos) {
+                for layer in TileLayer::ALL {
+                    spawn_events.send_batch(chunk.layer(layer).iter().map(|tile_data| {
+                        SpawnBlockEvent {
+                            layer,
+                            tile_set: tile_data.tile_set.clone(),
+                            tile_index: tile_data.tile_index,
+                            material: tile_data.material(),
+                            tile_pos: IVec2::new(tile_data.tile_pos.x, tile_data.tile_pos.y),
+                        }
+                    }));
+                }
+            }
+        }
+    }
+}
+
+/// Components of items that get frozen while their chunk isn't ready.
+type FreezableItem = (
+    Entity,
+    &'static Transform,
+    &'static mut RigidBody,
+    &'static mut Velocity,
+    Option<&'static Frozen>,
+);
+
+/// System that freezes items whose chunk isn't loaded, and unfreezes them
+/// once it is and it has a collider for them to land on. The tiles of a
+/// chunk only spawn after the chunk is loaded, and its collider after that.
+fn item_freeze_system(
+    mut commands: Commands,
+    definitions: Res<TileSetDefinitions>,
+    loaded_chunks: Res<LoadedChunks>,
+    chunk_colliders: Res<ChunkColliders>,
+    world_store: Option<ResMut<WorldStore>>,
+    mut item_query: Query<FreezableItem, With<Item>>,
+) {
+    // No world has been loaded yet
+    let mut world_store = match world_store {
+        Some(world_store) => world_store,
+        None => return,
+    };
+
+    for (item_entity, item_tf, mut rigid_body, mut velocity, frozen) in item_query.iter_mut() {
+        let chunk_pos = coords::world_to_chunk(item_tf.translation.truncate());
+
+        let chunk_ready = loaded_chunks.contains(chunk_pos)
+            && (chunk_colliders.contains(chunk_pos)
+                || !has_solid_blocks(&mut world_store, &definitions, chunk_pos));
+
+        match (chunk_ready, frozen) {
+            (false, None) => {
+                *rigid_body = RigidBody::Fixed;
+                commands.entity(item_entity).insert(Frozen(*velocity));
+            }
+            (true, Some(frozen)) => {
+                *rigid_body = RigidBody::Dynamic;
+                *velocity = frozen.0;
+                commands.entity(item_entity).remove::<Frozen>();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether a chunk has any solid blocks, and so will get a collider once it's loaded.
+fn has_solid_blocks(
+    world_store: &mut WorldStore,
+    definitions: &TileSetDefinitions,
+    chunk_pos: IVec2,
+) -> bool {
+    world_store.chunk(chunk_pos).is_some_and(|chunk| {
+        chunk.blocks.iter().any(|block| {
+            block
+                .properties(definitions)
+                .is_some_and(|properties| properties.solid)
+        })
+    })
+}
+
+/// Copies a tile in a layer of the `TileMap` into the `WorldStore`.
+fn store_tile(world_store: &mut WorldStore, tile_map: &TileMap, layer: TileLayer, tile_pos: IVec2) {
+    world_store.set_tile(layer, tile_pos, tile_map.get(layer, tile_pos));
+}
@@ -1,6 +1,7 @@
 use bevy::prelude::*;
+use bevy_rapier2d::prelude::Velocity;
 
-use crate::GameState;
+use crate::{liquids::LiquidKind, GameState};
 
 // UI Components
 
@@ -27,8 +28,14 @@ pub enum MainMenuButton {
     NewGame,
     LoadGame,
     Options,
+    RestoreBackup,
+    StartFresh,
+    DismissError,
 }
 
+#[derive(Component)]
+pub struct MainMenuErrorDialog;
+
 #[derive(Component)]
 pub struct InventoryMenuParent;
 
@@ -44,6 +51,9 @@ pub struct InventorySlot {
 #[derive(Component)]
 pub struct InventorySlotBG;
 
+#[derive(Component)]
+pub struct SaveIndicator;
+
 // UI Components
 
 // Entity Types
@@ -51,12 +61,6 @@ pub struct InventorySlotBG;
 #[derive(Component)]
 pub struct MainCamera;
 
-#[derive(Component)]
-pub struct Block {
-    pub tile_set: String,
-    pub tile_index: usize,
-}
-
 #[derive(Component)]
 pub struct Player {
     pub animation_timer: Timer,
@@ -117,13 +121,91 @@ pub struct PlayerAttractor {
     pub strength: f32,
 }
 
-#[derive(Component, Default)]
+#[derive(Component, Clone, Default)]
 pub struct Inventory {
-    pub slots: Vec<(String, usize)>,
+    /// Items in each slot, `None` for slots that have been emptied. Slots
+    /// keep their place when others are emptied, so that the selected slot
+    /// keeps holding the same item. Slots past the end are empty as well.
+    pub slots: Vec<Option<(String, usize)>>,
     pub max_slots: usize,
+    /// Slot of the item the player is holding.
+    pub selected_slot: usize,
+}
+
+impl Inventory {
+    /// Name of the item the player is holding, if the selected slot isn't empty.
+    pub fn selected_item(&self) -> Option<&str> {
+        self.slots
+            .get(self.selected_slot)?
+            .as_ref()
+            .map(|(item_name, _)| item_name.as_str())
+    }
+
+    /// Items in the slots that aren't empty.
+    pub fn items(&self) -> impl Iterator<Item = &(String, usize)> {
+        self.slots.iter().flatten()
+    }
+
+    /// Whether an item that doesn't fit into a stack would have nowhere to go.
+    pub fn is_full(&self) -> bool {
+        self.slots.len() >= self.max_slots && self.slots.iter().all(Option::is_some)
+    }
+
+    /// Adds one of an item, onto a stack of it with room for more or
+    /// else into the first empty slot. Returns whether it fit.
+    pub fn add(&mut self, item_name: &str, stack_size: usize) -> bool {
+        let existing_stack = self
+            .slots
+            .iter_mut()
+            .flatten()
+            .find(|(slot_item, item_count)| slot_item == item_name && *item_count < stack_size);
+        if let Some((_, item_count)) = existing_stack {
+            *item_count += 1;
+            return true;
+        }
+
+        let new_stack = Some((item_name.to_owned(), 1));
+        if let Some(empty_slot) = self.slots.iter_mut().find(|slot| slot.is_none()) {
+            *empty_slot = new_stack;
+        } else if self.slots.len() < self.max_slots {
+            self.slots.push(new_stack);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Takes one of the held item out of the inventory, emptying
+    /// its slot if it was the last one.
+    pub fn consume_selected(&mut self) {
+        if let Some(slot) = self.slots.get_mut(self.selected_slot) {
+            if let Some((_, item_count)) = slot {
+                *item_count -= 1;
+
+                if *item_count == 0 {
+                    *slot = None;
+                }
+            }
+        }
+    }
 }
 
 #[derive(Component)]
 pub struct SpriteSize(pub Vec2);
 
+/// Holds a body still while its chunk isn't loaded, remembering
+/// the velocity it had so that it can carry on when unfrozen.
+#[derive(Component)]
+pub struct Frozen(pub Velocity);
+
+/// Marks a body whose center is in a tile with enough liquid to swim in.
+#[derive(Component)]
+pub struct Submerged(pub LiquidKind);
+
+#[derive(Component)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
 // Entity Components
@@ -0,0 +1,242 @@
+//! Conversions between the coordinate spaces used by the game:
+//!
+//! - Tile positions are integer grid coordinates, with tile (0, 0) centered
+//!   on the world origin.
+//! - World positions are the translations used by transforms and physics.
+//! - Chunk positions are integer coordinates of `CHUNK_SIZE` by `CHUNK_SIZE`
+//!   groups of tiles, with chunk (0, 0) holding tiles (0, 0) to (15, 15).
+//! - Screen positions are window coordinates with the origin in the bottom
+//!   left corner, like `Window::cursor_position`.
+
+use bevy::{prelude::*, render::camera::Camera};
+
+use crate::{tile_map::BLOCK_SIZE, SPRITE_SCALE};
+
+/// Width and height of a chunk, in tiles.
+pub const CHUNK_SIZE: i32 = 16;
+
+/// Width and height of a tile in world units.
+pub const TILE_SIZE: f32 = BLOCK_SIZE * SPRITE_SCALE;
+
+/// World position of the center of a tile.
+pub fn tile_to_world(tile_pos: IVec2) -> Vec2 {
+    tile_pos.as_vec2() * TILE_SIZE
+}
+
+/// Tile that a world position lies in. Positions exactly on the
+/// edge between two tiles belong to the upper/right tile.
+pub fn world_to_tile(world_pos: Vec2) -> IVec2 {
+    (world_pos / TILE_SIZE + Vec2::splat(0.5))
+        .floor()
+        .as_ivec2()
+}
+
+/// Chunk that a tile belongs to.
+pub fn tile_to_chunk(tile_pos: IVec2) -> IVec2 {
+    IVec2::new(
+        tile_pos.x.div_euclid(CHUNK_SIZE),
+        tile_pos.y.div_euclid(CHUNK_SIZE),
+    )
+}
+
+/// Position of a tile relative to the bottom left tile of its chunk,
+/// always between 0 and `CHUNK_SIZE - 1`.
+pub fn tile_to_local(tile_pos: IVec2) -> IVec2 {
+    IVec2::new(
+        tile_pos.x.rem_euclid(CHUNK_SIZE),
+        tile_pos.y.rem_euclid(CHUNK_SIZE),
+    )
+}
+
+/// Bottom left tile of a chunk.
+pub fn chunk_to_tile(chunk_pos: IVec2) -> IVec2 {
+    chunk_pos * CHUNK_SIZE
+}
+
+/// Every tile position in a chunk, row by row from the bottom left tile.
+pub fn chunk_tiles(chunk_pos: IVec2) -> impl Iterator<Item = IVec2> {
+    let first_tile = chunk_to_tile(chunk_pos);
+
+    (0..CHUNK_SIZE).flat_map(move |local_y| {
+        (0..CHUNK_SIZE).map(move |local_x| first_tile + IVec2::new(local_x, local_y))
+    })
+}
+
+/// Chunk that a world position lies in.
+pub fn world_to_chunk(world_pos: Vec2) -> IVec2 {
+    tile_to_chunk(world_to_tile(world_pos))
+}
+
+/// World position seen at a point of a window of the given size through the camera.
+pub fn screen_to_world(
+    screen_pos: Vec2,
+    window_size: Vec2,
+    camera: &Camera,
+    camera_tf: &GlobalTransform,
+) -> Vec2 {
+    let ndc = screen_pos / window_size * 2. - Vec2::ONE;
+
+    let ndc_to_world = camera_tf.compute_matrix() * camera.projection_matrix.inverse();
+    ndc_to_world.project_point3(ndc.extend(-1.)).truncate()
+}
+
+/// Tile under the cursor in the primary window, seen through the given
+/// camera. `None` if the cursor isn't in the window.
+pub fn cursor_tile(
+    windows: &Windows,
+    camera: &Camera,
+    camera_tf: &GlobalTransform,
+) -> Option<IVec2> {
+    let window = windows.get_primary()?;
+    let window_size = Vec2::new(window.width(), window.height());
+    let world_pos = screen_to_world(window.cursor_position()?, window_size, camera, camera_tf);
+
+    Some(world_to_tile(world_pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::camera::{CameraProjection, OrthographicProjection};
+
+    use super::*;
+
+    /// Tiles on both sides of chunk borders, including the negative ones.
+    const EDGE_TILES: [i32; 9] = [
+        -CHUNK_SIZE - 1,
+        -CHUNK_SIZE,
+        -CHUNK_SIZE + 1,
+        -1,
+        0,
+        1,
+        CHUNK_SIZE - 1,
+        CHUNK_SIZE,
+        CHUNK_SIZE + 1,
+    ];
+
+    #[test]
+    fn tile_world_round_trip() {
+        for y in EDGE_TILES {
+            for x in EDGE_TILES {
+                let tile_pos = IVec2::new(x, y);
+                assert_eq!(world_to_tile(tile_to_world(tile_pos)), tile_pos);
+            }
+        }
+    }
+
+    #[test]
+    fn world_to_tile_rounds_to_nearest_tile() {
+        for x in EDGE_TILES {
+            let center = tile_to_world(IVec2::new(x, 0)).x;
+            let below_edge = 0.5 * TILE_SIZE - 0.01;
+
+            // Anything short of half a tile away stays in the tile
+            assert_eq!(world_to_tile(Vec2::new(center + below_edge, 0.)).x, x);
+            assert_eq!(world_to_tile(Vec2::new(center - below_edge, 0.)).x, x);
+
+            // Edges belong to the upper/right tile
+            let edge = Vec2::new(center + 0.5 * TILE_SIZE, center + 0.5 * TILE_SIZE);
+            assert_eq!(world_to_tile(edge), IVec2::new(x + 1, x + 1));
+            let edge = Vec2::new(center - 0.5 * TILE_SIZE, center - 0.5 * TILE_SIZE);
+            assert_eq!(world_to_tile(edge), IVec2::new(x, x));
+        }
+    }
+
+    #[test]
+    fn tile_to_chunk_rounds_down() {
+        let chunk_x = |x| tile_to_chunk(IVec2::new(x, 0)).x;
+
+        assert_eq!(chunk_x(-CHUNK_SIZE - 1), -2);
+        assert_eq!(chunk_x(-CHUNK_SIZE), -1);
+        assert_eq!(chunk_x(-1), -1);
+        assert_eq!(chunk_x(0), 0);
+        assert_eq!(chunk_x(CHUNK_SIZE - 1), 0);
+        assert_eq!(chunk_x(CHUNK_SIZE), 1);
+    }
+
+    #[test]
+    fn tile_chunk_round_trip() {
+        for y in EDGE_TILES {
+            for x in EDGE_TILES {
+                let tile_pos = IVec2::new(x, y);
+                let local_pos = tile_to_local(tile_pos);
+
+                assert!((0..CHUNK_SIZE).contains(&local_pos.x));
+                assert!((0..CHUNK_SIZE).contains(&local_pos.y));
+                assert_eq!(chunk_to_tile(tile_to_chunk(tile_pos)) + local_pos, tile_pos);
+                assert!(chunk_tiles(tile_to_chunk(tile_pos)).any(|tile| tile == tile_pos));
+            }
+        }
+    }
+
+    #[test]
+    fn world_chunk_round_trip() {
+        for y in EDGE_TILES {
+            for x in EDGE_TILES {
+                let tile_pos = IVec2::new(x, y);
+                let world_pos = tile_to_world(tile_pos);
+
+                assert_eq!(world_to_chunk(world_pos), tile_to_chunk(tile_pos));
+                // The bottom left corner of the chunk's first tile is still in the chunk
+                let chunk_corner = tile_to_world(chunk_to_tile(tile_to_chunk(tile_pos)))
+                    - Vec2::splat(0.5 * TILE_SIZE);
+                assert_eq!(world_to_chunk(chunk_corner), tile_to_chunk(tile_pos));
+            }
+        }
+    }
+
+    /// Window size and camera of the game's 2D camera, zoomed out by `scale`.
+    fn camera(scale: f32) -> (Vec2, Camera) {
+        let window_size = Vec2::new(1280., 720.);
+        let mut projection = OrthographicProjection {
+            scale,
+            ..Default::default()
+        };
+        projection.update(window_size.x, window_size.y);
+
+        let camera = Camera {
+            projection_matrix: projection.get_projection_matrix(),
+            ..Default::default()
+        };
+        (window_size, camera)
+    }
+
+    #[test]
+    fn window_center_shows_the_camera_position() {
+        let (window_size, camera) = camera(1.);
+
+        for camera_pos in [Vec2::ZERO, Vec2::new(-250., 1000.5)] {
+            // Same depth as the 2D camera bundle
+            let camera_tf = GlobalTransform::from_translation(camera_pos.extend(999.9));
+            let world_pos = screen_to_world(window_size / 2., window_size, &camera, &camera_tf);
+
+            assert!(world_pos.abs_diff_eq(camera_pos, 0.001), "{:?}", world_pos);
+        }
+    }
+
+    #[test]
+    fn screen_to_world_with_camera_offset() {
+        let (window_size, camera) = camera(1.);
+        let camera_pos = Vec2::new(-250., 1000.5);
+        let camera_tf = GlobalTransform::from_translation(camera_pos.extend(999.9));
+
+        // One pixel is one world unit, with the origin in the bottom left corner
+        for screen_pos in [Vec2::ZERO, Vec2::new(1., 700.), window_size] {
+            let world_pos = screen_to_world(screen_pos, window_size, &camera, &camera_tf);
+            let expected = camera_pos + screen_pos - window_size / 2.;
+
+            assert!(world_pos.abs_diff_eq(expected, 0.001), "{:?}", world_pos);
+        }
+    }
+
+    #[test]
+    fn screen_to_world_with_zoomed_out_camera() {
+        let (window_size, camera) = camera(2.);
+        let camera_pos = Vec2::new(64., -32.);
+        let camera_tf = GlobalTransform::from_translation(camera_pos.extend(999.9));
+
+        let world_pos = screen_to_world(Vec2::ZERO, window_size, &camera, &camera_tf);
+        let expected = camera_pos - window_size;
+
+        assert!(world_pos.abs_diff_eq(expected, 0.001), "{:?}", world_pos);
+    }
+}
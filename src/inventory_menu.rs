@@ -73,7 +73,7 @@ fn inventory_menu_setup_system(
                 })
                 .insert(InventoryMenu)
                 .with_children(|inventory| {
-                    for (item_name, item_count) in &player_inv.slots {
+                    for (item_name, item_count) in player_inv.items() {
                         if let Some(item_data) = items.get(item_name) {
                             inventory
                                 .spawn_bundle(ButtonBundle {
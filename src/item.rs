@@ -4,7 +4,7 @@ use serde::Deserialize;
 // use bevy_rapier2d::prelude::*;
 
 use crate::{
-    components::{Item, SpriteSize},
+    components::{Item, PlayerAttractor, SpriteSize},
     GameState, SPRITE_SCALE,
 };
 
@@ -17,6 +17,10 @@ pub type Items = HashMap<String, ItemData>;
 pub struct ItemData {
     pub item_type: ItemType,
     pub stack_size: usize,
+    /// Brightness of the light given off by the item, from 0 to 1, both
+    /// while it lies in the world and while the player holds it.
+    #[serde(default)]
+    pub light_emission: f32,
 
     #[serde(skip)]
     pub sprite: Handle<Image>,
@@ -40,11 +44,40 @@ pub enum ItemType {
         tile_set: String,
         tile_index: usize,
     },
+    Wall {
+        tile_set: String,
+        tile_index: usize,
+    },
+    Pickaxe {
+        /// A block takes its tile set's hardness in seconds
+        /// to mine with a mining speed of 1.
+        mining_speed: f32,
+    },
 }
 
 pub struct SpawnItemEvent {
     pub item_name: String,
     pub position: Vec2,
+    /// Rotation around the z axis, in radians.
+    pub rotation: f32,
+    pub velocity: Velocity,
+    pub picked_up: bool,
+    /// Strength of the `PlayerAttractor` pulling the item towards the player, if any.
+    pub attractor_strength: Option<f32>,
+}
+
+impl Default for SpawnItemEvent {
+    /// An item at rest that hasn't been picked up.
+    fn default() -> Self {
+        Self {
+            item_name: String::new(),
+            position: Vec2::ZERO,
+            rotation: 0.,
+            velocity: Velocity::zero(),
+            picked_up: false,
+            attractor_strength: None,
+        }
+    }
 }
 
 pub struct ItemPlugin;
@@ -57,6 +90,22 @@ impl Plugin for ItemPlugin {
     }
 }
 
+/// Names of every item that has a directory in `assets/items`.
+pub fn item_names() -> std::io::Result<Vec<String>> {
+    let mut item_names = Vec::new();
+
+    for entry in std::fs::read_dir(ITEMS_DIR)? {
+        let entry = entry?;
+
+        if let (true, Some(item_name)) = (entry.path().is_dir(), entry.file_name().to_str()) {
+            item_names.push(item_name.to_owned());
+        }
+    }
+
+    item_names.sort();
+    Ok(item_names)
+}
+
 fn item_setup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
     let items = std::fs::read_dir(ITEMS_DIR)
         .unwrap()
@@ -106,16 +155,17 @@ fn item_spawn_system(
 ) {
     for spawn_item in events.iter() {
         if let Some(item_data) = items.get(&spawn_item.item_name) {
-            commands
-                .spawn_bundle(SpriteBundle {
-                    texture: item_data.sprite.clone(),
-                    transform: Transform {
-                        translation: Vec3::new(spawn_item.position.x, spawn_item.position.y, 0.0),
-                        scale: Vec3::new(ITEM_SPRITE_SCALE, ITEM_SPRITE_SCALE, 1.),
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
+            let mut item_commands = commands.spawn_bundle(SpriteBundle {
+                texture: item_data.sprite.clone(),
+                transform: Transform {
+                    translation: Vec3::new(spawn_item.position.x, spawn_item.position.y, 0.0),
+                    rotation: Quat::from_rotation_z(spawn_item.rotation),
+                    scale: Vec3::new(ITEM_SPRITE_SCALE, ITEM_SPRITE_SCALE, 1.),
+                },
+                ..Default::default()
+            });
+
+            item_commands
                 .insert(RigidBody::Dynamic)
                 .insert(Collider::round_cuboid(
                     36.0 * ITEM_SPRITE_SCALE - 5.0,
@@ -126,13 +176,20 @@ fn item_spawn_system(
                     mass: 2.0,
                     ..Default::default()
                 })
-                .insert(Velocity::zero())
+                .insert(spawn_item.velocity)
                 .insert(Damping {
                     linear_damping: 0.25,
                     angular_damping: 0.25,
                 })
-                .insert(Item::new(&spawn_item.item_name))
+                .insert(Item {
+                    picked_up: spawn_item.picked_up,
+                    ..Item::new(&spawn_item.item_name)
+                })
                 .insert(SpriteSize(Vec2::new(72., 72.)));
+
+            if let Some(strength) = spawn_item.attractor_strength {
+                item_commands.insert(PlayerAttractor { strength });
+            }
         } else {
             eprintln!("Tried to spawn undefined item: {}", spawn_item.item_name);
         }
@@ -0,0 +1,141 @@
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+use bevy_rapier2d::prelude::*;
+
+use auto_tile::AutoTilePlugin;
+use block_placing::BlockPlacingPlugin;
+use chunk_colliders::ChunkCollidersPlugin;
+use chunk_mesh::ChunkMeshPlugin;
+use chunk_streaming::ChunkStreamingPlugin;
+use components::MainCamera;
+use inventory_menu::InventoryMenuPlugin;
+use item::ItemPlugin;
+use lighting::LightingPlugin;
+use liquids::LiquidsPlugin;
+use main_menu::MainMenuPlugin;
+use mining::MiningPlugin;
+use player::PlayerPlugin;
+use save_data::SaveDataPlugin;
+use tile_map::TileMapPlugin;
+use world_clock::WorldClockPlugin;
+
+pub mod auto_tile;
+mod block_placing;
+mod chunk_colliders;
+mod chunk_mesh;
+mod chunk_streaming;
+mod components;
+pub mod coords;
+mod inventory_menu;
+pub mod item;
+pub mod lighting;
+pub mod liquids;
+mod main_menu;
+mod mining;
+mod player;
+pub mod save_data;
+pub mod tile_map;
+pub mod world_clock;
+pub mod world_gen;
+
+const TIME_STEP: f32 = 1.0 / 60.0;
+const SPRITE_SCALE: f32 = 2.5;
+
+const PIXELLARI_FONT: &str = "fonts/Pixellari.ttf";
+const BUTTON_SPRITE: &str = "ui/button/button.png";
+const BUTTON_PRESSED_SPRITE: &str = "ui/button/button_pressed.png";
+const INVENTORY_SLOT_SPRITE: &str = "ui/inventory/inventory_slot.png";
+const INVENTORY_SLOT_SELECTED_SPRITE: &str = "ui/inventory/inventory_slot_selected.png";
+const INVENTORY_BG_SPRITE: &str = "ui/inventory/inventory_bg.png";
+
+struct UIAssets {
+    font: Handle<Font>,
+    button: Handle<Image>,
+    button_pressed: Handle<Image>,
+    inventory_slot: Handle<Image>,
+    inventory_slot_selected: Handle<Image>,
+    inventory_bg: Handle<Image>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum GameState {
+    MainMenu,
+    NewGameMenu,
+    OptionsMenu,
+    Game,
+    Inventory,
+}
+
+/// Starts the game. Lives in the library so that tools like `terons-save`
+/// can share the game's modules.
+pub fn run() {
+    App::new()
+        .insert_resource(ClearColor(Color::hex("87CEEB").unwrap()))
+        .insert_resource(WindowDescriptor {
+            title: "Teron's Crusade".to_owned(),
+            width: 1080.,
+            height: 720.,
+            ..Default::default()
+        })
+        .add_state(GameState::MainMenu)
+        .add_plugins(DefaultPlugins)
+        .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(
+            SPRITE_SCALE,
+        ))
+        .add_plugin(MainMenuPlugin)
+        .add_plugin(InventoryMenuPlugin)
+        .add_plugin(PlayerPlugin)
+        .add_plugin(TileMapPlugin)
+        .add_plugin(ChunkStreamingPlugin)
+        .add_plugin(AutoTilePlugin)
+        .add_plugin(LightingPlugin)
+        .add_plugin(LiquidsPlugin)
+        .add_plugin(ChunkCollidersPlugin)
+        .add_plugin(ChunkMeshPlugin)
+        .add_plugin(SaveDataPlugin)
+        .add_plugin(WorldClockPlugin)
+        .add_plugin(ItemPlugin)
+        .add_plugin(MiningPlugin)
+        .add_plugin(BlockPlacingPlugin)
+        .add_startup_system(setup_system)
+        .add_startup_system(ui_assets_setup_system)
+        .run();
+}
+
+/// Run criteria to chain after a `FixedTimestep`, so that fixed-step systems
+/// only run while `GameState::Game` is the current state. A system set can't
+/// use both `SystemSet::on_update` and `FixedTimestep`, as the second run
+/// criteria replaces the first.
+fn in_game(In(should_run): In<ShouldRun>, state: Res<State<GameState>>) -> ShouldRun {
+    if *state.current() == GameState::Game {
+        should_run
+    } else {
+        ShouldRun::No
+    }
+}
+
+fn setup_system(mut commands: Commands) {
+    // Add camera bundles
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(MainCamera);
+    commands.spawn_bundle(UiCameraBundle::default());
+
+    // Add Rapier configurations
+    let rapier_config = RapierConfiguration {
+        gravity: Vec2::new(0., -1500.),
+        ..Default::default()
+    };
+    commands.insert_resource(rapier_config);
+}
+
+fn ui_assets_setup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let ui_assets = UIAssets {
+        font: asset_server.load(PIXELLARI_FONT),
+        button: asset_server.load(BUTTON_SPRITE),
+        button_pressed: asset_server.load(BUTTON_PRESSED_SPRITE),
+        inventory_slot: asset_server.load(INVENTORY_SLOT_SPRITE),
+        inventory_slot_selected: asset_server.load(INVENTORY_SLOT_SELECTED_SPRITE),
+        inventory_bg: asset_server.load(INVENTORY_BG_SPRITE),
+    };
+    commands.insert_resource(ui_assets);
+}
@@ -0,0 +1,605 @@
+//! Tile-based lighting, computed on the CPU. Sunlight shines down every column
+//! of tiles until it reaches a solid block, light-emitting tiles and items
+//! add their own light, and light spreads out from there, growing dimmer with
+//! every tile it passes and faster through solid blocks. Chunk meshes are
+//! tinted with the light of their tiles, and items and the player with the
+//! light of the tile they're in. Sunlight follows the time of day on the
+//! `WorldClock`.
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    chunk_streaming::LoadedChunks,
+    components::{Inventory, Item, Player},
+    coords,
+    item::Items,
+    save_data::WorldStore,
+    tile_map::{TileChangedEvent, TileLayer, TileMap, TileSetDefinitions},
+    world_clock::WorldClock,
+    world_gen, GameState,
+};
+
+/// Light level of a fully lit tile. Tiles without any light have a level of 0.
+pub const MAX_LIGHT: u8 = 15;
+
+/// Light level of tiles open to the sky during the day and at night.
+/// Sunlight reaches every tile above the highest solid block of its column.
+const DAY_SUNLIGHT: u8 = MAX_LIGHT;
+const NIGHT_SUNLIGHT: u8 = 4;
+
+/// Light lost when spreading into an open tile.
+const AIR_FALLOFF: u8 = 1;
+/// Light lost when spreading into a tile with a solid block.
+const BLOCK_FALLOFF: u8 = 3;
+
+/// Sent after the light of any tile in a chunk has changed.
+pub struct ChunkLightChangedEvent {
+    pub chunk_pos: IVec2,
+}
+
+/// Light level of every tile in the loaded chunks.
+#[derive(Default)]
+pub struct LightMap {
+    /// Only tiles with some light are stored.
+    levels: HashMap<IVec2, u8>,
+    /// Loaded chunks whose light has been computed.
+    lit_chunks: HashSet<IVec2>,
+    /// Light given off by items, by the tile they were in at the last update.
+    item_lights: HashMap<IVec2, u8>,
+    /// Light level of tiles open to the sky at the last update.
+    sunlight: u8,
+}
+
+impl LightMap {
+    /// Light level of a tile, 0 if it isn't in a lit chunk.
+    pub fn level(&self, tile_pos: IVec2) -> u8 {
+        self.levels.get(&tile_pos).copied().unwrap_or(0)
+    }
+}
+
+/// Brightness of a light level, from 0 to 1.
+pub fn brightness(light_level: u8) -> f32 {
+    light_level as f32 / MAX_LIGHT as f32
+}
+
+/// Light level given off by something with a light emission from 0 to 1.
+fn emission_level(light_emission: f32) -> u8 {
+    (light_emission.clamp(0., 1.) * MAX_LIGHT as f32).round() as u8
+}
+
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ChunkLightChangedEvent>()
+            .init_resource::<LightMap>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Game)
+                    .with_system(light_update_system)
+                    .with_system(entity_light_system),
+            );
+    }
+}
+
+/// System that recomputes the light around changed tiles, light-emitting
+/// items that have moved and newly loaded chunks, or of every chunk once
+/// the sunlight has grown brighter or dimmer. Light never spreads
+/// further than `MAX_LIGHT` tiles, less than a chunk, so only the chunks
+/// next to a change can have their light changed by it.
+fn light_update_system(
+    mut sources: LightSources,
+    loaded_chunks: Res<LoadedChunks>,
+    world_clock: Option<Res<WorldClock>>,
+    mut light_map: ResMut<LightMap>,
+    mut changed_events: EventReader<TileChangedEvent>,
+    mut light_events: EventWriter<ChunkLightChangedEvent>,
+) {
+    let mut changed_chunks: HashSet<IVec2> = changed_events
+        .iter()
+        .map(|changed| coords::tile_to_chunk(changed.tile_pos))
+        .collect();
+
+    // Blocks shade every tile below them from the sun
+    let shaded_chunks: Vec<IVec2> = loaded_chunks
+        .iter()
+        .filter(|chunk_pos| {
+            changed_chunks
+                .iter()
+                .any(|changed| changed.x == chunk_pos.x && changed.y > chunk_pos.y)
+        })
+        .collect();
+    changed_chunks.extend(shaded_chunks);
+
+    // Forget the light of unloaded chunks, and light newly loaded ones
+    let unloaded_chunks: Vec<IVec2> = light_map
+        .lit_chunks
+        .iter()
+        .copied()
+        .filter(|chunk_pos| !loaded_chunks.contains(*chunk_pos))
+        .collect();
+    for chunk_pos in unloaded_chunks {
+        light_map.lit_chunks.remove(&chunk_pos);
+        for tile_pos in coords::chunk_tiles(chunk_pos) {
+            light_map.levels.remove(&tile_pos);
+        }
+    }
+    changed_chunks.extend(
+        loaded_chunks
+            .iter()
+            .filter(|chunk_pos| !light_map.lit_chunks.contains(chunk_pos)),
+    );
+
+    let daylight = world_clock.map_or(1., |world_clock| world_clock.daylight());
+    let sunlight =
+        NIGHT_SUNLIGHT + ((DAY_SUNLIGHT - NIGHT_SUNLIGHT) as f32 * daylight).round() as u8;
+    if sunlight != light_map.sunlight {
+        light_map.sunlight = sunlight;
+        changed_chunks.extend(loaded_chunks.iter());
+    }
+
+    let old_item_lights = std::mem::replace(&mut light_map.item_lights, sources.item_lights());
+    let item_lights = &light_map.item_lights;
+    changed_chunks.extend(
+        item_lights
+            .keys()
+            .chain(old_item_lights.keys())
+            .filter(|tile_pos| item_lights.get(tile_pos) != old_item_lights.get(tile_pos))
+            .map(|tile_pos| coords::tile_to_chunk(*tile_pos)),
+    );
+
+    let dirty_chunks = with_neighbour_chunks(&changed_chunks, &loaded_chunks);
+    if dirty_chunks.is_empty() {
+        return;
+    }
+
+    // Light can reach the dirty chunks from anywhere in the chunks around them
+    let area_chunks = with_neighbour_chunks(&dirty_chunks, &loaded_chunks);
+
+    let sky_heights = sources.sky_heights(&area_chunks, &loaded_chunks);
+
+    let mut lit_tiles = Vec::new();
+    for tile_pos in area_chunks.iter().copied().flat_map(coords::chunk_tiles) {
+        let open_to_sky = sky_heights
+            .get(&tile_pos.x)
+            .is_none_or(|sky_height| tile_pos.y > *sky_height);
+        let item_light = light_map.item_lights.get(&tile_pos).copied().unwrap_or(0);
+
+        let light_level = [
+            if open_to_sky { sunlight } else { 0 },
+            sources.tile_emission(tile_pos),
+            item_light,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(0);
+        if light_level > 0 {
+            lit_tiles.push((tile_pos, light_level));
+        }
+    }
+
+    let levels = spread_light(lit_tiles, |tile_pos| {
+        if !area_chunks.contains(&coords::tile_to_chunk(tile_pos)) {
+            return None;
+        }
+
+        Some(if sources.is_solid(tile_pos) {
+            BLOCK_FALLOFF
+        } else {
+            AIR_FALLOFF
+        })
+    });
+
+    for chunk_pos in dirty_chunks {
+        let mut light_changed = !light_map.lit_chunks.contains(&chunk_pos);
+        light_map.lit_chunks.insert(chunk_pos);
+
+        for tile_pos in coords::chunk_tiles(chunk_pos) {
+            let light_level = levels.get(&tile_pos).copied().unwrap_or(0);
+            if light_map.level(tile_pos) == light_level {
+                continue;
+            }
+
+            light_changed = true;
+            if light_level > 0 {
+                light_map.levels.insert(tile_pos, light_level);
+            } else {
+                light_map.levels.remove(&tile_pos);
+            }
+        }
+
+        if light_changed {
+            light_events.send(ChunkLightChangedEvent { chunk_pos });
+        }
+    }
+}
+
+/// The tiles and items that give off light or block it.
+#[derive(SystemParam)]
+struct LightSources<'w, 's> {
+    definitions: Res<'w, TileSetDefinitions>,
+    items: Res<'w, Items>,
+    tile_map: Res<'w, TileMap>,
+    /// Holds the tiles of chunks that aren't loaded. Missing until a world has been loaded.
+    world_store: Option<ResMut<'w, WorldStore>>,
+    item_query: Query<'w, 's, (&'static Transform, &'static Item)>,
+    player_query: Query<'w, 's, (&'static Transform, &'static Inventory), With<Player>>,
+}
+
+impl LightSources<'_, '_> {
+    /// Light given off by items lying in the world and the item held by the
+    /// player, by the tile they're in.
+    fn item_lights(&self) -> HashMap<IVec2, u8> {
+        let item_emission = |item_name: &str| {
+            self.items
+                .get(item_name)
+                .map_or(0, |item_data| emission_level(item_data.light_emission))
+        };
+        let held_items = self
+            .player_query
+            .iter()
+            .filter_map(|(player_tf, player_inv)| Some((player_tf, player_inv.selected_item()?)));
+        let lit_items = self
+            .item_query
+            .iter()
+            .map(|(item_tf, item)| (item_tf, item.item_name.as_str()))
+            .chain(held_items);
+
+        let mut item_lights = HashMap::<IVec2, u8>::default();
+        for (tf, item_name) in lit_items {
+            let light_level = item_emission(item_name);
+            if light_level > 0 {
+                let light = item_lights
+                    .entry(coords::world_to_tile(tf.translation.truncate()))
+                    .or_default();
+                *light = (*light).max(light_level);
+            }
+        }
+
+        item_lights
+    }
+
+    /// Light given off by the block and wall in a tile, whichever is brighter.
+    fn tile_emission(&self, tile_pos: IVec2) -> u8 {
+        TileLayer::ALL
+            .into_iter()
+            .filter_map(|layer| self.tile_map.get(layer, tile_pos))
+            .filter_map(|tile| self.definitions.properties(tile))
+            .map(|properties| emission_level(properties.light_emission))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn is_solid(&self, tile_pos: IVec2) -> bool {
+        self.tile_map
+            .get(TileLayer::Block, tile_pos)
+            .and_then(|tile| self.definitions.properties(tile))
+            .is_some_and(|properties| properties.solid)
+    }
+
+    /// Height of the highest solid block in every column of tiles of the
+    /// given chunks. See `sky_heights`.
+    fn sky_heights(
+        &mut self,
+        chunks: &HashSet<IVec2>,
+        loaded_chunks: &LoadedChunks,
+    ) -> HashMap<i32, i32> {
+        // Blocks can be placed above the generated world
+        let top_chunk = |chunk_x| {
+            loaded_chunks
+                .iter()
+                .filter(|chunk_pos| chunk_pos.x == chunk_x)
+                .map(|chunk_pos| chunk_pos.y)
+                .fold(world_gen::WORLD_CHUNKS_Y.end - 1, i32::max)
+        };
+
+        sky_heights(chunks, top_chunk, |chunk_pos| {
+            self.solid_blocks(chunk_pos, loaded_chunks)
+        })
+    }
+
+    /// Positions of the solid blocks in a chunk, read from the `WorldStore`
+    /// if the chunk isn't loaded.
+    fn solid_blocks(&mut self, chunk_pos: IVec2, loaded_chunks: &LoadedChunks) -> Vec<IVec2> {
+        if loaded_chunks.contains(chunk_pos) {
+            return coords::chunk_tiles(chunk_pos)
+                .filter(|tile_pos| self.is_solid(*tile_pos))
+                .collect();
+        }
+
+        let definitions: &TileSetDefinitions = &self.definitions;
+        let chunk = match self
+            .world_store
+            .as_deref_mut()
+            .and_then(|world_store| world_store.chunk(chunk_pos))
+        {
+            Some(chunk) => chunk,
+            None => return Vec::new(),
+        };
+
+        chunk
+            .blocks
+            .iter()
+            .filter(|block| {
+                block
+                    .properties(definitions)
+                    .is_some_and(|properties| properties.solid)
+            })
+            .map(|block| IVec2::new(block.tile_pos.x, block.tile_pos.y))
+            .collect()
+    }
+}
+
+/// Tints items and the player with the light of the tile they're in.
+fn entity_light_system(
+    light_map: Res<LightMap>,
+    mut item_query: Query<(&Transform, &mut Sprite), With<Item>>,
+    mut player_query: Query<(&Transform, &mut TextureAtlasSprite), With<Player>>,
+) {
+    let light_color = |tf: &Transform| {
+        let tile_brightness =
+            brightness(light_map.level(coords::world_to_tile(tf.translation.truncate())));
+        Color::rgb(tile_brightness, tile_brightness, tile_brightness)
+    };
+
+    for (item_tf, mut sprite) in item_query.iter_mut() {
+        sprite.color = light_color(item_tf);
+    }
+
+    for (player_tf, mut sprite) in player_query.iter_mut() {
+        sprite.color = light_color(player_tf);
+    }
+}
+
+/// The given chunks plus the chunks around them, leaving out chunks that aren't loaded.
+fn with_neighbour_chunks(chunks: &HashSet<IVec2>, loaded_chunks: &LoadedChunks) -> HashSet<IVec2> {
+    chunks
+        .iter()
+        .flat_map(|chunk_pos| {
+            (-1..=1).flat_map(move |y| (-1..=1).map(move |x| *chunk_pos + IVec2::new(x, y)))
+        })
+        .filter(|chunk_pos| loaded_chunks.contains(*chunk_pos))
+        .collect()
+}
+
+/// Height of the highest solid block in every column of tiles of the given
+/// chunks, looking down from the chunk `top_chunk` gives for their column of
+/// chunks. `solid_blocks` gives the positions of the solid blocks in a chunk.
+/// Columns without any solid block down to the lowest of the chunks are left out.
+pub fn sky_heights(
+    chunks: &HashSet<IVec2>,
+    top_chunk: impl Fn(i32) -> i32,
+    mut solid_blocks: impl FnMut(IVec2) -> Vec<IVec2>,
+) -> HashMap<i32, i32> {
+    // Lowest of the chunks in each column of chunks
+    let mut bottom_chunks = HashMap::<i32, i32>::default();
+    for chunk_pos in chunks {
+        let bottom_chunk = bottom_chunks.entry(chunk_pos.x).or_insert(chunk_pos.y);
+        *bottom_chunk = (*bottom_chunk).min(chunk_pos.y);
+    }
+
+    let mut sky_heights = HashMap::<i32, i32>::default();
+    for (chunk_x, bottom_chunk) in bottom_chunks {
+        for chunk_y in (bottom_chunk..=top_chunk(chunk_x)).rev() {
+            let chunk_pos = IVec2::new(chunk_x, chunk_y);
+
+            for tile_pos in solid_blocks(chunk_pos) {
+                // Columns shaded by a higher chunk already have a higher block
+                let sky_height = sky_heights.entry(tile_pos.x).or_insert(tile_pos.y);
+                *sky_height = (*sky_height).max(tile_pos.y);
+            }
+
+            let first_tile = coords::chunk_to_tile(chunk_pos);
+            let all_shaded = (first_tile.x..first_tile.x + coords::CHUNK_SIZE)
+                .all(|x| sky_heights.contains_key(&x));
+            if all_shaded {
+                break;
+            }
+        }
+    }
+
+    sky_heights
+}
+
+/// Spreads light from `sources`, given as tile positions and their light
+/// levels, to the tiles around them. `falloff` gives the light lost when
+/// spreading into a tile, or `None` for tiles that light can't spread into.
+/// Returns the light level of every tile that ends up with some light.
+pub fn spread_light(
+    sources: impl IntoIterator<Item = (IVec2, u8)>,
+    falloff: impl Fn(IVec2) -> Option<u8>,
+) -> HashMap<IVec2, u8> {
+    let mut levels = HashMap::<IVec2, u8>::default();
+    // Tiles waiting to spread their light, by light level
+    let mut queues = vec![Vec::new(); MAX_LIGHT as usize + 1];
+
+    for (tile_pos, light_level) in sources {
+        let light_level = light_level.min(MAX_LIGHT);
+        if light_level > levels.get(&tile_pos).copied().unwrap_or(0) {
+            levels.insert(tile_pos, light_level);
+            queues[light_level as usize].push(tile_pos);
+        }
+    }
+
+    // Brightest tiles go first, so every tile's final level is
+    // known by the time it spreads its light any further
+    for light_level in (1..=MAX_LIGHT).rev() {
+        while let Some(tile_pos) = queues[light_level as usize].pop() {
+            // Tiles that were queued again with more light have already spread it
+            if levels.get(&tile_pos) != Some(&light_level) {
+                continue;
+            }
+
+            for offset in [[0, 1], [0, -1], [-1, 0], [1, 0]] {
+                let neighbour = tile_pos + IVec2::from(offset);
+                let neighbour_level = match falloff(neighbour) {
+                    Some(falloff) => light_level.saturating_sub(falloff),
+                    None => continue,
+                };
+
+                if neighbour_level > levels.get(&neighbour).copied().unwrap_or(0) {
+                    levels.insert(neighbour, neighbour_level);
+                    queues[neighbour_level as usize].push(neighbour);
+                }
+            }
+        }
+    }
+
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coords::CHUNK_SIZE;
+
+    /// Falloff of tiles in a 32 by 32 area around the origin, with solid blocks in `solid_tiles`.
+    fn falloff(solid_tiles: &[IVec2]) -> impl Fn(IVec2) -> Option<u8> + '_ {
+        |tile_pos: IVec2| {
+            if tile_pos.abs().max_element() > 16 {
+                None
+            } else if solid_tiles.contains(&tile_pos) {
+                Some(BLOCK_FALLOFF)
+            } else {
+                Some(AIR_FALLOFF)
+            }
+        }
+    }
+
+    fn level(levels: &HashMap<IVec2, u8>, x: i32, y: i32) -> u8 {
+        levels.get(&IVec2::new(x, y)).copied().unwrap_or(0)
+    }
+
+    #[test]
+    fn sky_light_stops_at_the_first_solid_block() {
+        let solid_tiles = [IVec2::new(3, 10), IVec2::new(3, 4), IVec2::new(5, 2)];
+        let chunks = HashSet::from_iter([IVec2::ZERO]);
+
+        let sky_heights = sky_heights(&chunks, |_| 0, |_| solid_tiles.to_vec());
+        assert_eq!(sky_heights.get(&3), Some(&10));
+        assert_eq!(sky_heights.get(&5), Some(&2));
+        // Nothing shades the other columns, all the way down
+        assert_eq!(sky_heights.get(&4), None);
+    }
+
+    #[test]
+    fn sky_light_is_shaded_by_chunks_above() {
+        let chunks = HashSet::from_iter([IVec2::ZERO]);
+        let mut read_chunks = Vec::new();
+
+        let sky_heights = sky_heights(
+            &chunks,
+            |_| 2,
+            |chunk_pos| {
+                read_chunks.push(chunk_pos);
+                match chunk_pos.y {
+                    // A floating island over the left half of the chunk
+                    2 => (0..CHUNK_SIZE / 2)
+                        .map(|x| IVec2::new(x, 2 * CHUNK_SIZE))
+                        .collect(),
+                    0 => vec![IVec2::new(1, 3), IVec2::new(CHUNK_SIZE - 1, 3)],
+                    _ => Vec::new(),
+                }
+            },
+        );
+
+        assert_eq!(sky_heights.get(&1), Some(&(2 * CHUNK_SIZE)));
+        assert_eq!(sky_heights.get(&(CHUNK_SIZE - 1)), Some(&3));
+        assert_eq!(sky_heights.get(&(CHUNK_SIZE / 2)), None);
+        assert_eq!(
+            read_chunks,
+            [IVec2::new(0, 2), IVec2::new(0, 1), IVec2::new(0, 0)]
+        );
+    }
+
+    #[test]
+    fn sky_light_stops_looking_down_once_every_column_is_shaded() {
+        let chunks = HashSet::from_iter([IVec2::ZERO]);
+        let mut read_chunks = Vec::new();
+
+        let sky_heights = sky_heights(
+            &chunks,
+            |_| 1,
+            |chunk_pos| {
+                read_chunks.push(chunk_pos);
+                let first_tile = coords::chunk_to_tile(chunk_pos);
+                (0..CHUNK_SIZE)
+                    .map(|x| first_tile + IVec2::new(x, 0))
+                    .collect()
+            },
+        );
+
+        assert_eq!(sky_heights.len(), CHUNK_SIZE as usize);
+        assert!(sky_heights.values().all(|height| *height == CHUNK_SIZE));
+        assert_eq!(read_chunks, [IVec2::new(0, 1)]);
+    }
+
+    #[test]
+    fn light_falls_off_with_every_tile() {
+        let levels = spread_light([(IVec2::ZERO, 10)], falloff(&[]));
+
+        assert_eq!(level(&levels, 0, 0), 10);
+        assert_eq!(level(&levels, 3, 0), 7);
+        assert_eq!(level(&levels, -2, -2), 6);
+        assert_eq!(level(&levels, 0, 9), 1);
+        assert_eq!(level(&levels, 0, 10), 0);
+        assert_eq!(levels.len(), 181, "light should fill a diamond");
+    }
+
+    #[test]
+    fn light_falls_off_faster_through_solid_blocks() {
+        let levels = spread_light([(IVec2::ZERO, 10)], falloff(&[IVec2::new(1, 0)]));
+
+        assert_eq!(level(&levels, 1, 0), 10 - BLOCK_FALLOFF);
+        // The tile behind the block is lit around it just as well
+        assert_eq!(level(&levels, 2, 0), 10 - BLOCK_FALLOFF - AIR_FALLOFF);
+    }
+
+    #[test]
+    fn light_floods_around_solid_blocks() {
+        // A wall three blocks thick and three high, right of the light
+        let wall: Vec<IVec2> = (1..=3)
+            .flat_map(|x| (-1..=1).map(move |y| IVec2::new(x, y)))
+            .collect();
+        let levels = spread_light([(IVec2::ZERO, 10)], falloff(&wall));
+
+        // Light passing through the wall has nearly run out on its far side
+        assert_eq!(level(&levels, 3, 0), 10 - 3 * BLOCK_FALLOFF);
+        // But the tile beyond it is lit by light that spread
+        // around the wall, through 8 tiles of air
+        assert_eq!(level(&levels, 4, 0), 10 - 8 * AIR_FALLOFF);
+    }
+
+    #[test]
+    fn light_cant_spread_where_falloff_is_none() {
+        let levels = spread_light([(IVec2::new(16, 0), 10)], falloff(&[]));
+
+        assert_eq!(level(&levels, 16, 0), 10);
+        assert_eq!(level(&levels, 17, 0), 0);
+        assert_eq!(level(&levels, 15, 0), 9);
+    }
+
+    #[test]
+    fn overlapping_lights_keep_the_brightest() {
+        let levels = spread_light(
+            [
+                (IVec2::ZERO, 10),
+                (IVec2::new(4, 0), 8),
+                (IVec2::ZERO, 5),
+                (IVec2::new(-20, 0), 20),
+            ],
+            falloff(&[]),
+        );
+
+        assert_eq!(level(&levels, 0, 0), 10);
+        assert_eq!(level(&levels, 2, 0), 8);
+        assert_eq!(level(&levels, 3, 0), 7);
+        assert_eq!(level(&levels, 4, 0), 8);
+        assert_eq!(level(&levels, 5, 0), 7);
+        // Sources are capped at `MAX_LIGHT`, even where light can't spread
+        assert_eq!(level(&levels, -20, 0), MAX_LIGHT);
+    }
+}
@@ -0,0 +1,724 @@
+//! Water and lava that fill tiles up to a level and flow as a cellular
+//! automaton. On every tick, liquid falls into the tile below it if it can,
+//! and otherwise spreads out to the sides until it is level. Water and lava
+//! that flow into each other turn into obsidian. Only liquids in loaded
+//! chunks flow, and every change is copied into the `WorldStore` so that
+//! liquids are saved with the rest of the world.
+
+use bevy::{
+    core::FixedTimestep,
+    prelude::*,
+    sprite::MaterialMesh2dBundle,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk_mesh::ChunkMeshData,
+    chunk_streaming::LoadedChunks,
+    components::{Health, Player, Submerged},
+    coords, in_game,
+    save_data::WorldStore,
+    tile_map::{
+        BlockSpawnLabel, SpawnBlockEvent, TileChangedEvent, TileLayer, TileMap, TileSetDefinitions,
+    },
+    GameState,
+};
+
+/// Level of a completely full tile.
+pub const MAX_LEVEL: u8 = 8;
+
+/// Seconds between flow ticks.
+const FLOW_TICK: f64 = 0.1;
+/// Lava is thick, so it only flows on every this many ticks.
+const LAVA_FLOW_TICKS: u64 = 3;
+
+/// Lowest level of liquid that the player swims in.
+const SWIM_LEVEL: u8 = MAX_LEVEL / 2;
+/// Health lost per second while swimming in lava.
+const LAVA_DAMAGE: f32 = 40.;
+
+/// Block that water and lava turn into when they meet.
+pub const OBSIDIAN_TILE_SET: &str = "obsidian";
+
+/// Depth of liquid meshes, in front of the blocks at a depth of 0.
+const LIQUID_Z: f32 = 0.5;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum LiquidKind {
+    Water,
+    Lava,
+}
+
+impl LiquidKind {
+    const ALL: [LiquidKind; 2] = [LiquidKind::Water, LiquidKind::Lava];
+}
+
+/// Liquid in a tile of the `LiquidMap`, filling it up to `level` out of `MAX_LEVEL`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Liquid {
+    pub kind: LiquidKind,
+    pub level: u8,
+}
+
+/// Sent after the liquid in any tile of a chunk has changed.
+pub struct LiquidChangedEvent {
+    pub chunk_pos: IVec2,
+}
+
+/// Liquid in every tile of the loaded chunks.
+#[derive(Default)]
+pub struct LiquidMap {
+    liquids: HashMap<IVec2, Liquid>,
+    /// Tiles whose liquid might flow on the next tick. Liquid
+    /// that didn't flow stays put until something around it changes.
+    active: HashSet<IVec2>,
+    /// Tiles whose liquid changed since it was last copied into the `WorldStore`.
+    changed: HashSet<IVec2>,
+    /// Loaded chunks whose liquids have been read from the `WorldStore`.
+    loaded_chunks: HashSet<IVec2>,
+    ticks: u64,
+}
+
+impl LiquidMap {
+    pub fn get(&self, tile_pos: IVec2) -> Option<Liquid> {
+        self.liquids.get(&tile_pos).copied()
+    }
+
+    /// Puts liquid into a tile, or empties it if `liquid` is `None`, and wakes
+    /// up the liquid around it.
+    fn set(&mut self, tile_pos: IVec2, liquid: Option<Liquid>) {
+        match liquid.filter(|liquid| liquid.level > 0) {
+            Some(liquid) => self.liquids.insert(tile_pos, liquid),
+            None => self.liquids.remove(&tile_pos),
+        };
+
+        self.changed.insert(tile_pos);
+        self.activate_around(tile_pos);
+    }
+
+    fn activate_around(&mut self, tile_pos: IVec2) {
+        self.active.insert(tile_pos);
+        for offset in [[0, 1], [0, -1], [-1, 0], [1, 0]] {
+            self.active.insert(tile_pos + IVec2::from(offset));
+        }
+    }
+
+    /// Lets every liquid that might be able to flow do so once, into the tiles
+    /// of loaded chunks for which `is_solid` is false. Returns the tiles that
+    /// water and lava have met in, which should be filled with obsidian.
+    fn flow(&mut self, is_solid: impl Fn(IVec2) -> bool) -> Vec<IVec2> {
+        self.ticks += 1;
+        let lava_flows = self.ticks.is_multiple_of(LAVA_FLOW_TICKS);
+        // Alternate the side liquid spreads to first, so that it doesn't drift one way
+        let sides = if self.ticks.is_multiple_of(2) {
+            [-1, 1]
+        } else {
+            [1, -1]
+        };
+
+        // Bottom rows go first, so that the liquid below has made room for falling liquid
+        let mut tiles: Vec<IVec2> = self.active.drain().collect();
+        tiles.sort_by_key(|tile_pos| (tile_pos.y, tile_pos.x * sides[0]));
+
+        let can_flow_into = |liquid_map: &LiquidMap, tile_pos: IVec2| {
+            liquid_map
+                .loaded_chunks
+                .contains(&coords::tile_to_chunk(tile_pos))
+                && !is_solid(tile_pos)
+        };
+
+        let mut solidified = Vec::new();
+
+        for tile_pos in tiles {
+            let liquid = match self.get(tile_pos) {
+                Some(liquid) => liquid,
+                None => continue,
+            };
+
+            if liquid.kind == LiquidKind::Lava && !lava_flows {
+                self.active.insert(tile_pos);
+                continue;
+            }
+
+            let mut level = liquid.level;
+
+            // Fall into the tile below, as far as it has room
+            let below = tile_pos + IVec2::new(0, -1);
+            if can_flow_into(self, below) {
+                match self.get(below) {
+                    Some(other) if other.kind != liquid.kind => {
+                        self.solidify(below, &mut solidified);
+                        level -= 1;
+                    }
+                    other => {
+                        let below_level = other.map_or(0, |other| other.level);
+                        let amount = level.min(MAX_LEVEL - below_level);
+
+                        if amount > 0 {
+                            let below_liquid = Liquid {
+                                kind: liquid.kind,
+                                level: below_level + amount,
+                            };
+                            self.set(below, Some(below_liquid));
+                            level -= amount;
+                        }
+                    }
+                }
+            }
+
+            // Spread out to the sides, into the nearest tile that is lower by more
+            // than one level. Liquid passes through tiles that are only one level
+            // lower to get there, as it would otherwise settle in steps.
+            for side in sides {
+                if level <= 1 {
+                    break;
+                }
+
+                let step = IVec2::new(side, 0);
+                let neighbour = tile_pos + step;
+                if !can_flow_into(self, neighbour) {
+                    continue;
+                }
+
+                if self
+                    .get(neighbour)
+                    .is_some_and(|other| other.kind != liquid.kind)
+                {
+                    self.solidify(neighbour, &mut solidified);
+                    level -= 1;
+                    continue;
+                }
+
+                let mut target = neighbour;
+                while self
+                    .get(target)
+                    .is_some_and(|other| other.kind == liquid.kind && other.level + 1 == level)
+                    && can_flow_into(self, target + step)
+                {
+                    target += step;
+                }
+
+                match self.get(target) {
+                    // Only liquid right next to other liquid mixes with it
+                    Some(other) if other.kind != liquid.kind => {}
+                    other => {
+                        let target_level = other.map_or(0, |other| other.level);
+
+                        if target_level + 1 < level {
+                            let target_liquid = Liquid {
+                                kind: liquid.kind,
+                                level: target_level + 1,
+                            };
+                            self.set(target, Some(target_liquid));
+                            level -= 1;
+                        }
+                    }
+                }
+            }
+
+            if level != liquid.level {
+                let liquid = Liquid {
+                    kind: liquid.kind,
+                    level,
+                };
+                self.set(tile_pos, Some(liquid));
+            }
+        }
+
+        solidified
+    }
+
+    /// Empties a tile that water and lava have met in, however full it was, as
+    /// the obsidian they make takes up the whole tile. The liquid that flowed
+    /// into it only loses the level that met the other liquid.
+    fn solidify(&mut self, tile_pos: IVec2, solidified: &mut Vec<IVec2>) {
+        self.set(tile_pos, None);
+        solidified.push(tile_pos);
+    }
+}
+
+struct LiquidMaterials {
+    water: Handle<ColorMaterial>,
+    lava: Handle<ColorMaterial>,
+}
+
+/// Mesh entities of every chunk that has liquids, one per kind of liquid in the chunk.
+#[derive(Default)]
+struct LiquidMeshes(HashMap<IVec2, Vec<Entity>>);
+
+pub struct LiquidsPlugin;
+
+impl Plugin for LiquidsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LiquidChangedEvent>()
+            .init_resource::<LiquidMap>()
+            .init_resource::<LiquidMeshes>()
+            .add_startup_system(liquid_setup_system)
+            .add_system_set(
+                SystemSet::on_update(GameState::Game)
+                    // Liquids read before the blocks of their chunk have
+                    // spawned would fall straight through the ground
+                    .with_system(liquid_streaming_system.after(BlockSpawnLabel))
+                    .with_system(liquid_mesh_system)
+                    .with_system(liquid_player_system),
+            )
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(FixedTimestep::step(FLOW_TICK).chain(in_game))
+                    .with_system(liquid_flow_system),
+            );
+    }
+}
+
+fn liquid_setup_system(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    commands.insert_resource(LiquidMaterials {
+        water: materials.add(ColorMaterial::from(Color::rgba(0.15, 0.4, 0.9, 0.6))),
+        lava: materials.add(ColorMaterial::from(Color::rgb(1., 0.4, 0.05))),
+    });
+}
+
+/// System that copies liquid changes into the `WorldStore`, reads the liquids
+/// of newly loaded chunks, forgets those of unloaded chunks, and lets liquid
+/// flow again around blocks that have changed.
+fn liquid_streaming_system(
+    world_store: Option<ResMut<WorldStore>>,
+    definitions: Res<TileSetDefinitions>,
+    tile_map: Res<TileMap>,
+    loaded_chunks: Res<LoadedChunks>,
+    mut liquid_map: ResMut<LiquidMap>,
+    mut tile_events: EventReader<TileChangedEvent>,
+    mut liquid_events: EventWriter<LiquidChangedEvent>,
+) {
+    // No world has been loaded yet
+    let mut world_store = match world_store {
+        Some(world_store) => world_store,
+        None => return,
+    };
+
+    let mut changed_chunks = HashSet::default();
+
+    let changed_tiles: Vec<IVec2> = liquid_map.changed.drain().collect();
+    for tile_pos in changed_tiles {
+        let liquid = liquid_map
+            .get(tile_pos)
+            .map(|liquid| (liquid.kind, liquid.level));
+        world_store.set_liquid(tile_pos, liquid);
+        changed_chunks.insert(coords::tile_to_chunk(tile_pos));
+    }
+
+    // Forget the liquids of unloaded chunks, which are already in the `WorldStore`
+    let unloaded_chunks: Vec<IVec2> = liquid_map
+        .loaded_chunks
+        .iter()
+        .copied()
+        .filter(|chunk_pos| !loaded_chunks.contains(*chunk_pos))
+        .collect();
+    for chunk_pos in unloaded_chunks {
+        liquid_map.loaded_chunks.remove(&chunk_pos);
+        for tile_pos in coords::chunk_tiles(chunk_pos) {
+            liquid_map.liquids.remove(&tile_pos);
+            liquid_map.active.remove(&tile_pos);
+        }
+        changed_chunks.insert(chunk_pos);
+    }
+
+    // Read the liquids of newly loaded chunks
+    for chunk_pos in loaded_chunks.iter() {
+        if !liquid_map.loaded_chunks.insert(chunk_pos) {
+            continue;
+        }
+
+        for liquid in world_store
+            .chunk(chunk_pos)
+            .into_iter()
+            .flat_map(|chunk| &chunk.liquids)
+        {
+            let tile_pos = IVec2::new(liquid.tile_pos.x, liquid.tile_pos.y);
+            liquid_map.liquids.insert(
+                tile_pos,
+                Liquid {
+                    kind: liquid.kind,
+                    level: liquid.level,
+                },
+            );
+        }
+
+        // Liquid at the edges of the chunks around it might be able to flow into it now
+        for tile_pos in coords::chunk_tiles(chunk_pos) {
+            liquid_map.activate_around(tile_pos);
+        }
+        changed_chunks.insert(chunk_pos);
+    }
+
+    for changed in tile_events.iter() {
+        if changed.layer != TileLayer::Block {
+            continue;
+        }
+
+        // Solid blocks push the liquid out of their tile
+        if is_solid(&tile_map, &definitions, changed.tile_pos)
+            && liquid_map.get(changed.tile_pos).is_some()
+        {
+            liquid_map.set(changed.tile_pos, None);
+        } else {
+            liquid_map.activate_around(changed.tile_pos);
+        }
+    }
+
+    liquid_events.send_batch(
+        changed_chunks
+            .into_iter()
+            .map(|chunk_pos| LiquidChangedEvent { chunk_pos }),
+    );
+}
+
+/// System that lets every liquid that might be able to flow do so once.
+fn liquid_flow_system(
+    definitions: Res<TileSetDefinitions>,
+    tile_map: Res<TileMap>,
+    mut liquid_map: ResMut<LiquidMap>,
+    mut spawn_events: EventWriter<SpawnBlockEvent>,
+) {
+    let solidified = liquid_map.flow(|tile_pos| is_solid(&tile_map, &definitions, tile_pos));
+
+    spawn_events.send_batch(solidified.into_iter().map(|tile_pos| SpawnBlockEvent {
+        layer: TileLayer::Block,
+        tile_set: OBSIDIAN_TILE_SET.to_owned(),
+        tile_index: 0,
+        material: 0,
+        tile_pos,
+    }));
+}
+
+fn is_solid(tile_map: &TileMap, definitions: &TileSetDefinitions, tile_pos: IVec2) -> bool {
+    tile_map
+        .get(TileLayer::Block, tile_pos)
+        .and_then(|tile| definitions.properties(tile))
+        .is_some_and(|properties| properties.solid)
+}
+
+/// System that rebuilds the meshes of chunks whose liquids have changed.
+fn liquid_mesh_system(
+    mut commands: Commands,
+    liquid_map: Res<LiquidMap>,
+    materials: Res<LiquidMaterials>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut liquid_meshes: ResMut<LiquidMeshes>,
+    mut changed_events: EventReader<LiquidChangedEvent>,
+) {
+    let changed_chunks: HashSet<IVec2> = changed_events
+        .iter()
+        .map(|changed| changed.chunk_pos)
+        .collect();
+
+    for chunk_pos in changed_chunks {
+        for mesh_entity in liquid_meshes.0.remove(&chunk_pos).into_iter().flatten() {
+            commands.entity(mesh_entity).despawn();
+        }
+
+        let first_tile = coords::chunk_to_tile(chunk_pos);
+        let mut mesh_entities = Vec::new();
+
+        for kind in LiquidKind::ALL {
+            let tiles: Vec<(IVec2, f32)> = coords::chunk_tiles(chunk_pos)
+                .filter_map(|tile_pos| {
+                    let liquid = liquid_map
+                        .get(tile_pos)
+                        .filter(|liquid| liquid.kind == kind)?;
+                    Some((
+                        tile_pos - first_tile,
+                        liquid.level as f32 / MAX_LEVEL as f32,
+                    ))
+                })
+                .collect();
+            if tiles.is_empty() {
+                continue;
+            }
+
+            let material = match kind {
+                LiquidKind::Water => &materials.water,
+                LiquidKind::Lava => &materials.lava,
+            };
+            let mesh = ChunkMeshData::build_filled(tiles).into_mesh();
+
+            mesh_entities.push(
+                commands
+                    .spawn_bundle(MaterialMesh2dBundle {
+                        mesh: meshes.add(mesh).into(),
+                        material: material.clone(),
+                        transform: Transform::from_translation(
+                            coords::tile_to_world(first_tile).extend(LIQUID_Z),
+                        ),
+                        ..Default::default()
+                    })
+                    .id(),
+            );
+        }
+
+        if !mesh_entities.is_empty() {
+            liquid_meshes.0.insert(chunk_pos, mesh_entities);
+        }
+    }
+}
+
+/// System that marks the player as `Submerged` while they're in deep enough
+/// liquid, and hurts them while that liquid is lava.
+fn liquid_player_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    liquid_map: Res<LiquidMap>,
+    mut player_query: Query<(Entity, &Transform, &mut Health, Option<&Submerged>), With<Player>>,
+) {
+    for (player_entity, player_tf, mut health, submerged) in player_query.iter_mut() {
+        let liquid = liquid_map
+            .get(coords::world_to_tile(player_tf.translation.truncate()))
+            .filter(|liquid| liquid.level >= SWIM_LEVEL);
+
+        match (liquid, submerged) {
+            (Some(liquid), submerged)
+                if submerged.map(|submerged| submerged.0) != Some(liquid.kind) =>
+            {
+                commands
+                    .entity(player_entity)
+                    .insert(Submerged(liquid.kind));
+            }
+            (None, Some(_)) => {
+                commands.entity(player_entity).remove::<Submerged>();
+            }
+            _ => {}
+        }
+
+        if liquid.is_some_and(|liquid| liquid.kind == LiquidKind::Lava) {
+            health.current -= LAVA_DAMAGE * time.delta_seconds();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Liquids and solid tiles of a loaded chunk drawn as rows of text from the
+    /// top row down, with `#` for solid tiles, the digits 1 to 8 for water of
+    /// that level and the letters `a` to `h` for lava of level 1 to 8.
+    fn from_drawing(rows: &[&str]) -> (LiquidMap, HashSet<IVec2>) {
+        let mut liquid_map = LiquidMap::default();
+        liquid_map.loaded_chunks.insert(IVec2::ZERO);
+        let mut solid_tiles = HashSet::default();
+
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, tile) in row.chars().enumerate() {
+                let tile_pos = IVec2::new(x as i32, y as i32);
+                let liquid = match tile {
+                    '#' => {
+                        solid_tiles.insert(tile_pos);
+                        continue;
+                    }
+                    '1'..='8' => Liquid {
+                        kind: LiquidKind::Water,
+                        level: tile as u8 - b'0',
+                    },
+                    'a'..='h' => Liquid {
+                        kind: LiquidKind::Lava,
+                        level: tile as u8 - b'a' + 1,
+                    },
+                    _ => continue,
+                };
+                liquid_map.set(tile_pos, Some(liquid));
+            }
+        }
+
+        (liquid_map, solid_tiles)
+    }
+
+    /// Draws the same rows as `from_drawing` does, after the liquids have flowed.
+    fn draw(liquid_map: &LiquidMap, solid_tiles: &HashSet<IVec2>, rows: &[&str]) -> Vec<String> {
+        (0..rows.len())
+            .rev()
+            .map(|y| {
+                (0..rows[0].len())
+                    .map(|x| {
+                        let tile_pos = IVec2::new(x as i32, y as i32);
+                        match liquid_map.get(tile_pos) {
+                            _ if solid_tiles.contains(&tile_pos) => '#',
+                            Some(Liquid {
+                                kind: LiquidKind::Water,
+                                level,
+                            }) => (b'0' + level) as char,
+                            Some(Liquid {
+                                kind: LiquidKind::Lava,
+                                level,
+                            }) => (b'a' + level - 1) as char,
+                            None => ' ',
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Lets the liquids of a drawing flow `ticks` times, returning
+    /// the tiles that turned into obsidian.
+    fn flow(liquid_map: &mut LiquidMap, solid_tiles: &HashSet<IVec2>, ticks: usize) -> Vec<IVec2> {
+        (0..ticks)
+            .flat_map(|_| liquid_map.flow(|tile_pos| solid_tiles.contains(&tile_pos)))
+            .collect()
+    }
+
+    fn total_level(liquid_map: &LiquidMap) -> u32 {
+        liquid_map
+            .liquids
+            .values()
+            .map(|liquid| liquid.level as u32)
+            .sum()
+    }
+
+    #[test]
+    fn falls_into_the_tile_below() {
+        let rows = ["#5#", "# #", "# #", "###"];
+        let (mut liquid_map, solid_tiles) = from_drawing(&rows);
+
+        flow(&mut liquid_map, &solid_tiles, 1);
+        assert_eq!(
+            draw(&liquid_map, &solid_tiles, &rows),
+            ["# #", "#5#", "# #", "###"]
+        );
+
+        flow(&mut liquid_map, &solid_tiles, 1);
+        assert_eq!(
+            draw(&liquid_map, &solid_tiles, &rows),
+            ["# #", "# #", "#5#", "###"]
+        );
+
+        // Resting on the ground, with nowhere to spread to
+        flow(&mut liquid_map, &solid_tiles, 1);
+        assert!(liquid_map.active.is_empty());
+    }
+
+    #[test]
+    fn falls_only_as_far_as_there_is_room() {
+        let rows = ["#6#", "#5#", "###"];
+        let (mut liquid_map, solid_tiles) = from_drawing(&rows);
+
+        flow(&mut liquid_map, &solid_tiles, 1);
+        assert_eq!(
+            draw(&liquid_map, &solid_tiles, &rows),
+            ["#3#", "#8#", "###"]
+        );
+    }
+
+    #[test]
+    fn spreads_to_lower_sides() {
+        let rows = ["#   #", "# 5 #", "#####"];
+        let (mut liquid_map, solid_tiles) = from_drawing(&rows);
+
+        flow(&mut liquid_map, &solid_tiles, 1);
+        assert_eq!(
+            draw(&liquid_map, &solid_tiles, &rows),
+            ["#   #", "#131#", "#####"]
+        );
+    }
+
+    #[test]
+    fn spreads_out_to_equal_levels() {
+        let rows = ["#8       #", "##########"];
+        let (mut liquid_map, solid_tiles) = from_drawing(&rows);
+
+        flow(&mut liquid_map, &solid_tiles, 50);
+        assert!(liquid_map.active.is_empty(), "liquid hasn't settled");
+        assert_eq!(
+            draw(&liquid_map, &solid_tiles, &rows),
+            ["#11111111#", "##########"]
+        );
+    }
+
+    #[test]
+    fn settles_to_equal_levels_in_a_basin() {
+        let rows = ["#    #", "#8 8 #", "######"];
+        let (mut liquid_map, solid_tiles) = from_drawing(&rows);
+
+        flow(&mut liquid_map, &solid_tiles, 50);
+        assert!(liquid_map.active.is_empty(), "liquid hasn't settled");
+        assert_eq!(
+            draw(&liquid_map, &solid_tiles, &rows),
+            ["#    #", "#4444#", "######"]
+        );
+    }
+
+    #[test]
+    fn settles_within_a_level_when_it_cant_be_equal() {
+        let rows = ["#         #", "#8   8   7#", "###########"];
+        let (mut liquid_map, solid_tiles) = from_drawing(&rows);
+
+        flow(&mut liquid_map, &solid_tiles, 100);
+        assert!(liquid_map.active.is_empty(), "liquid hasn't settled");
+        assert_eq!(total_level(&liquid_map), 23);
+
+        let levels: Vec<u8> = (1..10)
+            .map(|x| {
+                liquid_map
+                    .get(IVec2::new(x, 1))
+                    .map_or(0, |liquid| liquid.level)
+            })
+            .collect();
+        assert!(
+            levels.iter().max().unwrap() - levels.iter().min().unwrap() <= 1,
+            "{:?}",
+            levels
+        );
+    }
+
+    #[test]
+    fn lava_only_flows_every_few_ticks() {
+        let rows = ["#e#", "# #", "###"];
+        let (mut liquid_map, solid_tiles) = from_drawing(&rows);
+
+        flow(&mut liquid_map, &solid_tiles, LAVA_FLOW_TICKS as usize - 1);
+        assert_eq!(draw(&liquid_map, &solid_tiles, &rows), rows);
+
+        flow(&mut liquid_map, &solid_tiles, 1);
+        assert_eq!(
+            draw(&liquid_map, &solid_tiles, &rows),
+            ["# #", "#e#", "###"]
+        );
+    }
+
+    #[test]
+    fn does_not_flow_into_unloaded_chunks() {
+        // The tile left of the drawing is in the chunk to the left, which isn't loaded
+        let rows = ["8  ", "###"];
+        let (mut liquid_map, mut solid_tiles) = from_drawing(&rows);
+        solid_tiles.insert(IVec2::new(-1, -1));
+
+        flow(&mut liquid_map, &solid_tiles, 50);
+        assert_eq!(liquid_map.get(IVec2::new(-1, 0)), None);
+        assert_eq!(total_level(&liquid_map), 8);
+    }
+
+    #[test]
+    fn water_falling_onto_lava_makes_obsidian() {
+        let rows = ["#5#", "#h#", "###"];
+        let (mut liquid_map, solid_tiles) = from_drawing(&rows);
+
+        let solidified = flow(&mut liquid_map, &solid_tiles, 1);
+        assert_eq!(solidified, [IVec2::new(1, 1)]);
+
+        // All of the lava is gone, but the water only lost the level that met it
+        assert_eq!(
+            draw(&liquid_map, &solid_tiles, &rows),
+            ["#4#", "# #", "###"]
+        );
+    }
+
+    #[test]
+    fn lava_spreading_into_water_makes_obsidian() {
+        let rows = ["#h1 #", "#####"];
+        let (mut liquid_map, solid_tiles) = from_drawing(&rows);
+
+        // The water can't spread any further, so it stays put until the lava flows
+        let solidified = flow(&mut liquid_map, &solid_tiles, LAVA_FLOW_TICKS as usize);
+        assert_eq!(solidified, [IVec2::new(2, 1)]);
+        assert_eq!(draw(&liquid_map, &solid_tiles, &rows), ["#g  #", "#####"]);
+    }
+}
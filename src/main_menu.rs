@@ -1,8 +1,9 @@
 use bevy::{prelude::*, ui::FocusPolicy};
 
 use crate::{
-    components::{MainMenu, MainMenuButton, MainMenuFader},
-    GameState, UIAssets,
+    components::{MainMenu, MainMenuButton, MainMenuErrorDialog, MainMenuFader},
+    save_data::{slots, ActiveWorld, WorldLoadFailedEvent},
+    world_gen, GameState, UIAssets,
 };
 
 pub struct MainMenuPlugin;
@@ -23,8 +24,15 @@ impl Plugin for MainMenuPlugin {
     }
 }
 
-// System that spawns the main menu UI.
-fn main_menu_setup_system(mut commands: Commands, ui_assets: Res<UIAssets>) {
+// System that spawns the main menu UI, along with an error dialog
+// if the game was sent back here because a world failed to load.
+fn main_menu_setup_system(
+    mut commands: Commands,
+    ui_assets: Res<UIAssets>,
+    mut load_failed_events: EventReader<WorldLoadFailedEvent>,
+) {
+    let load_failed = load_failed_events.iter().last();
+
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -64,53 +72,182 @@ fn main_menu_setup_system(mut commands: Commands, ui_assets: Res<UIAssets>) {
                 ..Default::default()
             });
 
-            parent
-                .spawn_bundle(ButtonBundle {
+            spawn_menu_button(
+                parent,
+                &ui_assets,
+                "Play",
+                Size::new(Val::Percent(20.), Val::Percent(10.)),
+                MainMenuButton::LoadGame,
+            );
+
+            if let Some(event) = load_failed {
+                spawn_error_dialog(parent, &ui_assets, event);
+            }
+        });
+}
+
+/// Spawns a dialog telling the player that their world couldn't be loaded.
+/// Corrupted worlds can be restored from a backup that still loads, or be
+/// started from scratch with their broken files moved aside. Worlds that
+/// failed for any other reason, like being saved by a newer version of the
+/// game, are left alone.
+fn spawn_error_dialog(
+    parent: &mut ChildBuilder,
+    ui_assets: &UIAssets,
+    event: &WorldLoadFailedEvent,
+) {
+    let corrupted = event.error.is_corruption();
+    let restorable = corrupted && slots::restorable_backup(&event.world_name).is_some();
+    let message = if restorable {
+        format!(
+            "World \"{}\" is corrupted, but it has a backup that can be restored.\n{}",
+            event.world_name, event.error
+        )
+    } else if corrupted {
+        format!(
+            "World \"{}\" is corrupted and has no backup that can be restored.\n{}",
+            event.world_name, event.error
+        )
+    } else {
+        format!(
+            "World \"{}\" could not be loaded.\n{}",
+            event.world_name, event.error
+        )
+    };
+
+    parent
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(20.)),
+                ..Default::default()
+            },
+            color: UiColor(Color::rgba(0., 0., 0., 0.6)),
+            ..Default::default()
+        })
+        .insert(MainMenuErrorDialog)
+        .with_children(|dialog| {
+            dialog.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    message,
+                    TextStyle {
+                        font: ui_assets.font.clone(),
+                        font_size: 20.,
+                        color: Color::WHITE,
+                    },
+                    TextAlignment {
+                        horizontal: HorizontalAlign::Center,
+                        vertical: VerticalAlign::Center,
+                    },
+                ),
+                style: Style {
+                    max_size: Size::new(Val::Px(600.), Val::Undefined),
+                    ..Default::default()
+                },
+                focus_policy: FocusPolicy::Pass,
+                ..Default::default()
+            });
+
+            dialog
+                .spawn_bundle(NodeBundle {
                     style: Style {
-                        align_self: AlignSelf::Center,
-                        align_items: AlignItems::Center,
                         justify_content: JustifyContent::Center,
-                        size: Size::new(Val::Percent(20.), Val::Percent(10.)),
-                        margin: Rect::all(Val::Auto),
+                        margin: Rect {
+                            top: Val::Px(20.),
+                            ..Default::default()
+                        },
                         ..Default::default()
                     },
                     color: UiColor(Color::NONE),
-
+                    focus_policy: FocusPolicy::Pass,
                     ..Default::default()
                 })
-                .insert(MainMenuButton::LoadGame)
-                .with_children(|button| {
-                    button.spawn_bundle(ImageBundle {
-                        image: UiImage(ui_assets.button.clone()),
-                        style: Style {
-                            position_type: PositionType::Absolute,
-                            size: Size::new(Val::Percent(100.), Val::Percent(100.)),
-                            ..Default::default()
-                        },
-                        focus_policy: FocusPolicy::Pass,
-                        ..Default::default()
-                    });
-
-                    button.spawn_bundle(TextBundle {
-                        text: Text::with_section(
-                            "Play",
-                            TextStyle {
-                                font: ui_assets.font.clone(),
-                                color: Color::WHITE,
-                                font_size: 32.,
-                            },
-                            TextAlignment {
-                                horizontal: HorizontalAlign::Center,
-                                vertical: VerticalAlign::Center,
-                            },
-                        ),
-                        focus_policy: FocusPolicy::Pass,
-                        ..Default::default()
-                    });
+                .with_children(|buttons| {
+                    let button_size = Size::new(Val::Px(200.), Val::Px(60.));
+                    if restorable {
+                        spawn_menu_button(
+                            buttons,
+                            ui_assets,
+                            "Restore Backup",
+                            button_size,
+                            MainMenuButton::RestoreBackup,
+                        );
+                    }
+                    if corrupted {
+                        spawn_menu_button(
+                            buttons,
+                            ui_assets,
+                            "Start Fresh",
+                            button_size,
+                            MainMenuButton::StartFresh,
+                        );
+                    }
+                    spawn_menu_button(
+                        buttons,
+                        ui_assets,
+                        "Back",
+                        button_size,
+                        MainMenuButton::DismissError,
+                    );
                 });
         });
 }
 
+fn spawn_menu_button(
+    parent: &mut ChildBuilder,
+    ui_assets: &UIAssets,
+    label: &str,
+    size: Size<Val>,
+    button: MainMenuButton,
+) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                align_self: AlignSelf::Center,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                size,
+                margin: Rect::all(Val::Auto),
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+
+            ..Default::default()
+        })
+        .insert(button)
+        .with_children(|button| {
+            button.spawn_bundle(ImageBundle {
+                image: UiImage(ui_assets.button.clone()),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    ..Default::default()
+                },
+                focus_policy: FocusPolicy::Pass,
+                ..Default::default()
+            });
+
+            button.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    label,
+                    TextStyle {
+                        font: ui_assets.font.clone(),
+                        color: Color::WHITE,
+                        font_size: 32.,
+                    },
+                    TextAlignment {
+                        horizontal: HorizontalAlign::Center,
+                        vertical: VerticalAlign::Center,
+                    },
+                ),
+                focus_policy: FocusPolicy::Pass,
+                ..Default::default()
+            });
+        });
+}
+
 // System that despawns the main menu when switching states.
 fn main_menu_unload_system(mut commands: Commands, query: Query<Entity, With<MainMenu>>) {
     if let Ok(entity) = query.get_single() {
@@ -122,8 +259,10 @@ fn main_menu_unload_system(mut commands: Commands, query: Query<Entity, With<Mai
 fn main_menu_interaction_system(
     mut commands: Commands,
     ui_assets: Res<UIAssets>,
+    active_world: Res<ActiveWorld>,
     interaction_query: Query<(&Children, &MainMenuButton, &Interaction), Changed<Interaction>>,
     main_menu_query: Query<Entity, With<MainMenu>>,
+    error_dialog_query: Query<Entity, With<MainMenuErrorDialog>>,
     mut image_query: Query<&mut UiImage>,
 ) {
     if let Ok(main_menu_entity) = main_menu_query.get_single() {
@@ -139,6 +278,25 @@ fn main_menu_interaction_system(
                         MainMenuButton::NewGame => GameState::NewGameMenu,
                         MainMenuButton::LoadGame => GameState::Game,
                         MainMenuButton::Options => GameState::OptionsMenu,
+                        MainMenuButton::RestoreBackup => {
+                            if let Err(e) = slots::restore_backup(&active_world.0) {
+                                eprintln!("Error restoring world {}: {}", active_world.0, e);
+                            }
+                            GameState::Game
+                        }
+                        MainMenuButton::StartFresh => {
+                            let seed = world_gen::seed_from_args();
+                            if let Err(e) = slots::start_fresh(&active_world.0, seed) {
+                                eprintln!("Error starting world {} fresh: {}", active_world.0, e);
+                            }
+                            GameState::Game
+                        }
+                        MainMenuButton::DismissError => {
+                            for dialog_entity in error_dialog_query.iter() {
+                                commands.entity(dialog_entity).despawn_recursive();
+                            }
+                            continue;
+                        }
                     };
 
                     commands.entity(main_menu_entity).with_children(|parent| {
@@ -0,0 +1,131 @@
+//! Breaking blocks by holding the left mouse button on them with a pickaxe.
+//! Walls are mined the same way once the block in front of them is gone.
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    components::{Inventory, MainCamera, Player},
+    coords,
+    item::{ItemType, Items, SpawnItemEvent},
+    player,
+    tile_map::{DespawnBlockEvent, TileLayer, TileMap, TileSetDefinitions},
+    GameState,
+};
+
+/// Speed that mined blocks' drops pop out of the ground at.
+const DROP_SPEED: f32 = 150.;
+
+/// Tile being mined and its layer, and how much of it has been mined so
+/// far from 0 to 1. Starts over whenever the player aims somewhere else.
+#[derive(Default)]
+struct MiningProgress {
+    tile: Option<(TileLayer, IVec2)>,
+    progress: f32,
+}
+
+pub struct MiningPlugin;
+
+impl Plugin for MiningPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MiningProgress>()
+            .add_system_set(SystemSet::on_update(GameState::Game).with_system(mining_system));
+    }
+}
+
+/// System that mines the block under the cursor, or the wall if there is no
+/// block, while the left mouse button is held with a pickaxe selected, and
+/// drops its item once it breaks.
+fn mining_system(
+    time: Res<Time>,
+    aim: MiningAim,
+    definitions: Res<TileSetDefinitions>,
+    tile_map: Res<TileMap>,
+    mut mining_progress: ResMut<MiningProgress>,
+    mut despawn_events: EventWriter<DespawnBlockEvent>,
+    mut item_events: EventWriter<SpawnItemEvent>,
+) {
+    let target = aim.target().and_then(|(tile_pos, mining_speed)| {
+        // Walls are behind blocks, so they can only be mined once the block is gone
+        let layer = TileLayer::ALL
+            .into_iter()
+            .find(|layer| tile_map.contains(*layer, tile_pos))?;
+        Some((layer, tile_pos, mining_speed))
+    });
+
+    let (layer, tile_pos, mining_speed) = match target {
+        Some(target) => target,
+        None => {
+            *mining_progress = MiningProgress::default();
+            return;
+        }
+    };
+
+    if mining_progress.tile != Some((layer, tile_pos)) {
+        *mining_progress = MiningProgress {
+            tile: Some((layer, tile_pos)),
+            progress: 0.,
+        };
+    }
+
+    let properties = tile_map
+        .get(layer, tile_pos)
+        .and_then(|tile| definitions.properties(tile));
+    let hardness = properties.map_or(0., |properties| properties.hardness);
+
+    mining_progress.progress += time.delta_seconds() * mining_speed / hardness;
+    if mining_progress.progress < 1. {
+        return;
+    }
+
+    *mining_progress = MiningProgress::default();
+    despawn_events.send(DespawnBlockEvent { layer, tile_pos });
+
+    let drop = properties.and_then(|properties| match layer {
+        TileLayer::Block => properties.drop.as_ref(),
+        TileLayer::Wall => properties.wall_drop.as_ref(),
+    });
+    if let Some(drop) = drop {
+        item_events.send(SpawnItemEvent {
+            item_name: drop.clone(),
+            position: coords::tile_to_world(tile_pos),
+            velocity: Velocity::linear(Vec2::new(0., DROP_SPEED)),
+            ..Default::default()
+        });
+    }
+}
+
+/// Everything that decides where the player is mining and how fast.
+#[derive(SystemParam)]
+struct MiningAim<'w, 's> {
+    mouse: Res<'w, Input<MouseButton>>,
+    windows: Res<'w, Windows>,
+    items: Res<'w, Items>,
+    player_query: Query<'w, 's, (&'static Transform, &'static Inventory), With<Player>>,
+    camera_query: Query<'w, 's, (&'static Camera, &'static GlobalTransform), With<MainCamera>>,
+}
+
+impl MiningAim<'_, '_> {
+    /// Tile under the cursor and the mining speed of the held pickaxe, if the
+    /// player is mining and the tile is within reach.
+    fn target(&self) -> Option<(IVec2, f32)> {
+        if !self.mouse.pressed(MouseButton::Left) {
+            return None;
+        }
+
+        let (player_tf, player_inv) = self.player_query.get_single().ok()?;
+        let mining_speed = match self.items.get(player_inv.selected_item()?)?.item_type {
+            ItemType::Pickaxe { mining_speed } => mining_speed,
+            _ => return None,
+        };
+
+        let (camera, camera_tf) = self.camera_query.get_single().ok()?;
+        let tile_pos = coords::cursor_tile(&self.windows, camera, camera_tf)?;
+
+        if !player::tile_in_reach(player_tf.translation.truncate(), tile_pos) {
+            return None;
+        }
+
+        Some((tile_pos, mining_speed))
+    }
+}
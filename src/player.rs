@@ -9,10 +9,12 @@ use bevy_rapier2d::prelude::*;
 
 use crate::{
     components::{
-        AnimationState, AnimationStates, Inventory, Item, MainCamera, Player, PlayerAttractor,
-        SpriteSize,
+        AnimationState, AnimationStates, Health, Inventory, Item, MainCamera, Player,
+        PlayerAttractor, SpriteSize, Submerged,
     },
+    coords,
     item::Items,
+    save_data::WorldSpawn,
     GameState, SPRITE_SCALE, TIME_STEP,
 };
 
@@ -24,6 +26,15 @@ const JUMP_SHEET: &str = "player/jump.png";
 const PLAYER_SPEED: f32 = 170.;
 const PLAYER_JUMP_SPEED: f32 = 530.;
 const PLAYER_REACH: f32 = 120.;
+const PLAYER_INVENTORY_SLOTS: usize = 9;
+pub const PLAYER_MAX_HEALTH: f32 = 100.;
+/// Multiplier of the player's speed while moving through liquid.
+const SWIM_SPEED_FACTOR: f32 = 0.5;
+/// Fastest the player sinks through liquid.
+const MAX_SINK_SPEED: f32 = 120.;
+// Size of the player's capsule collider, before scaling by `SPRITE_SCALE`
+pub const PLAYER_HALF_HEIGHT: f32 = 8.;
+pub const PLAYER_RADIUS: f32 = 9.;
 
 struct PlayerTextures {
     pub idle: Handle<TextureAtlas>,
@@ -34,6 +45,43 @@ struct PlayerTextures {
 
 pub struct SpawnPlayerEvent {
     pub position: Vec3,
+    pub velocity: Vec2,
+    pub facing_left: bool,
+    pub health: f32,
+    pub inventory: Inventory,
+}
+
+impl Default for SpawnPlayerEvent {
+    /// A brand new player with an empty inventory.
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            velocity: Vec2::ZERO,
+            facing_left: false,
+            health: PLAYER_MAX_HEALTH,
+            inventory: Inventory {
+                slots: Vec::default(),
+                max_slots: PLAYER_INVENTORY_SLOTS,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Whether a tile is close enough to the player to be mined or built on.
+pub fn tile_in_reach(player_pos: Vec2, tile_pos: IVec2) -> bool {
+    coords::tile_to_world(tile_pos).distance(player_pos) <= PLAYER_REACH
+}
+
+/// Whether a block at the tile would overlap the player's collider.
+pub fn tile_overlaps_player(player_pos: Vec2, tile_pos: IVec2) -> bool {
+    let player_half_size =
+        Vec2::new(PLAYER_RADIUS, PLAYER_HALF_HEIGHT + PLAYER_RADIUS) * SPRITE_SCALE;
+    let distance = (coords::tile_to_world(tile_pos) - player_pos).abs();
+
+    distance
+        .cmplt(player_half_size + Vec2::splat(coords::TILE_SIZE / 2.))
+        .all()
 }
 
 pub struct PlayerPlugin;
@@ -48,6 +96,7 @@ impl Plugin for PlayerPlugin {
                     .with_run_criteria(FixedTimestep::step(TIME_STEP as f64))
                     .with_system(player_camera_follow_system)
                     .with_system(player_movement_system)
+                    .with_system(player_respawn_system)
                     .with_system(player_attractor_system)
                     .with_system(player_item_collision_system),
             )
@@ -55,7 +104,8 @@ impl Plugin for PlayerPlugin {
                 SystemSet::on_update(GameState::Game)
                     .with_system(spawn_player_system)
                     .with_system(player_animation_system)
-                    .with_system(player_item_pickup_system),
+                    .with_system(player_item_pickup_system)
+                    .with_system(player_slot_selection_system),
             );
     }
 }
@@ -100,6 +150,7 @@ fn spawn_player_system(
     mut events: EventReader<SpawnPlayerEvent>,
     player_textures: Res<PlayerTextures>,
     player_query: Query<(), With<Player>>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
 ) {
     if !player_query.is_empty() {
         return;
@@ -114,21 +165,32 @@ fn spawn_player_system(
                     scale: Vec3::new(SPRITE_SCALE, SPRITE_SCALE, 1.),
                     ..Default::default()
                 },
+                sprite: TextureAtlasSprite {
+                    flip_x: spawn_player.facing_left,
+                    ..Default::default()
+                },
                 ..Default::default()
             })
             .insert(RigidBody::Dynamic)
-            .insert(Collider::capsule_y(8., 9.))
+            .insert(Collider::capsule_y(PLAYER_HALF_HEIGHT, PLAYER_RADIUS))
             .insert(MassProperties {
                 mass: 10.0,
                 ..Default::default()
             })
-            .insert(Velocity::zero())
+            .insert(Velocity::linear(spawn_player.velocity))
             .insert(Player::default())
             .insert(AnimationState::default())
-            .insert(Inventory {
-                slots: Vec::default(),
-                max_slots: 9,
-            });
+            .insert(Health {
+                current: spawn_player.health,
+                max: PLAYER_MAX_HEALTH,
+            })
+            .insert(spawn_player.inventory.clone());
+
+        // Start the camera on the player so that chunks around them are loaded first
+        if let Ok(mut camera_tf) = camera_query.get_single_mut() {
+            camera_tf.translation.x = spawn_player.position.x;
+            camera_tf.translation.y = spawn_player.position.y;
+        }
 
         break;
     }
@@ -189,25 +251,34 @@ fn player_movement_system(
         ),
         With<Player>,
     >,
+    submerged_query: Query<(), (With<Player>, With<Submerged>)>,
 ) {
     if let Ok((mut transform, mut velocity, mut anim_state, mut sprite)) = query.get_single_mut() {
+        let submerged = !submerged_query.is_empty();
+
         // Keep angular velocity and rotation fixed
         velocity.angvel = 0.;
         transform.rotation = Quat::zeroed();
 
+        let speed_factor = if submerged { SWIM_SPEED_FACTOR } else { 1. };
+
         // Horizontal movement
         let direction = kb.pressed(KeyCode::D).into_integer() as f32
             - kb.pressed(KeyCode::A).into_integer() as f32;
-        velocity.linvel.x = direction * PLAYER_SPEED;
+        velocity.linvel.x = direction * PLAYER_SPEED * speed_factor;
 
-        // Jumping
-        if (kb.just_pressed(KeyCode::W) || kb.just_pressed(KeyCode::Space))
-            && -2. < velocity.linvel.y
-            && velocity.linvel.y < 2.
-        {
+        // Jumping, or swimming up which works at any time
+        let jump_pressed = kb.just_pressed(KeyCode::W) || kb.just_pressed(KeyCode::Space);
+        if jump_pressed && submerged {
+            velocity.linvel.y = PLAYER_JUMP_SPEED * speed_factor;
+        } else if jump_pressed && -2. < velocity.linvel.y && velocity.linvel.y < 2. {
             velocity.linvel.y += PLAYER_JUMP_SPEED;
         }
 
+        if submerged {
+            velocity.linvel.y = velocity.linvel.y.max(-MAX_SINK_SPEED);
+        }
+
         // Orient sprite in correct direction
         if direction > 0. {
             sprite.flip_x = false;
@@ -234,6 +305,28 @@ fn player_movement_system(
     }
 }
 
+/// System that sends the player back to the world spawn
+/// with full health once they run out of health.
+fn player_respawn_system(
+    world_spawn: Option<Res<WorldSpawn>>,
+    mut player_query: Query<(&mut Transform, &mut Velocity, &mut Health), With<Player>>,
+) {
+    let world_spawn = match world_spawn {
+        Some(world_spawn) => world_spawn,
+        None => return,
+    };
+
+    for (mut player_tf, mut velocity, mut health) in player_query.iter_mut() {
+        if health.current > 0. {
+            continue;
+        }
+
+        player_tf.translation = world_spawn.0;
+        *velocity = Velocity::zero();
+        health.current = health.max;
+    }
+}
+
 /// System that handles item pickups by the player
 fn player_item_pickup_system(
     kb: Res<Input<KeyCode>>,
@@ -243,7 +336,7 @@ fn player_item_pickup_system(
 ) {
     if kb.just_pressed(KeyCode::F) {
         if let Ok((player_tf, player_inv)) = player_query.get_single() {
-            if player_inv.slots.len() >= player_inv.max_slots {
+            if player_inv.is_full() {
                 return;
             }
 
@@ -263,6 +356,30 @@ fn player_item_pickup_system(
     }
 }
 
+/// System that selects the held inventory slot with the number keys
+fn player_slot_selection_system(
+    kb: Res<Input<KeyCode>>,
+    mut player_query: Query<&mut Inventory, With<Player>>,
+) {
+    const SLOT_KEYS: [KeyCode; PLAYER_INVENTORY_SLOTS] = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ];
+
+    if let Ok(mut player_inv) = player_query.get_single_mut() {
+        if let Some(slot) = SLOT_KEYS.iter().position(|key| kb.just_pressed(*key)) {
+            player_inv.selected_slot = slot;
+        }
+    }
+}
+
 fn player_item_collision_system(
     mut commands: Commands,
     texture_atlases: Res<Assets<TextureAtlas>>,
@@ -281,7 +398,7 @@ fn player_item_collision_system(
 
         for (item_entity, item_tf, item_size, mut item) in item_query.iter_mut() {
             // Remove PlayerAttractor and "unpick" item if inventory is full now
-            if player_inv.slots.len() >= player_inv.max_slots {
+            if player_inv.is_full() {
                 commands.entity(item_entity).remove::<PlayerAttractor>();
                 item.picked_up = false;
             }
@@ -300,23 +417,14 @@ fn player_item_collision_system(
             );
 
             if col.is_some() {
-                if let Some(item_data) = items.get(&item.item_name) {
-                    let inv: &mut Vec<(String, usize)> = &mut player_inv.slots;
-
-                    // Find a slot which has the same item and has space for more items
-                    let existing_slot_pos = inv.iter().position(|(item_name, item_count)| {
-                        *item_name == item.item_name && *item_count < item_data.stack_size
-                    });
-
-                    if let Some(slot_idx) = existing_slot_pos {
-                        let existing_slot = inv.get_mut(slot_idx).unwrap();
-                        existing_slot.1 += 1;
-                    } else {
-                        inv.push((item.item_name.clone(), 1));
-                    }
-                }
+                // Unknown items can't be held, so they just disappear
+                let added = items
+                    .get(&item.item_name)
+                    .is_none_or(|item_data| player_inv.add(&item.item_name, item_data.stack_size));
 
-                commands.entity(item_entity).despawn();
+                if added {
+                    commands.entity(item_entity).despawn();
+                }
             }
         }
     }
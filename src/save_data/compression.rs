@@ -0,0 +1,37 @@
+use std::{
+    borrow::Cow,
+    io::{Read, Write},
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder};
+
+use super::SaveError;
+
+/// First byte of a compressed payload, naming the method it was compressed with.
+const UNCOMPRESSED: u8 = 0;
+const ZLIB: u8 = 1;
+
+/// Compresses a payload, prefixing it with the compression method.
+pub fn compress(bytes: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let mut encoder = ZlibEncoder::new(vec![ZLIB], flate2::Compression::default());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
+
+/// Decompresses a payload written by `compress`.
+pub fn decompress(bytes: &[u8]) -> Result<Cow<'_, [u8]>, SaveError> {
+    match bytes.split_first() {
+        Some((&UNCOMPRESSED, payload)) => Ok(Cow::Borrowed(payload)),
+        Some((&ZLIB, payload)) => {
+            let mut decompressed = Vec::new();
+            ZlibDecoder::new(payload).read_to_end(&mut decompressed)?;
+            Ok(Cow::Owned(decompressed))
+        }
+        Some((method, _)) => Err(SaveError::Corrupted(Box::new(bincode::ErrorKind::Custom(
+            format!("Unknown compression method: {}", method),
+        )))),
+        None => Err(SaveError::Corrupted(Box::new(bincode::ErrorKind::Custom(
+            "Missing compression method".to_owned(),
+        )))),
+    }
+}
@@ -0,0 +1,79 @@
+use std::{fmt, io};
+
+/// Everything that can go wrong while reading, writing or managing world saves.
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Corrupted(bincode::Error),
+    UnsupportedVersion(u32),
+    InvalidWorldName(String),
+    WorldNotFound(String),
+    WorldAlreadyExists(String),
+    /// None of the world's backups can be loaded either.
+    NoValidBackup(String),
+    /// New worlds can't be generated without the auto-tiling rules of this tile set.
+    MissingTileSet(String),
+}
+
+impl SaveError {
+    /// Whether save files were read but couldn't be decoded. Unlike other
+    /// errors, this is fixed by starting the world over from scratch.
+    pub fn is_corruption(&self) -> bool {
+        match self {
+            SaveError::Corrupted(_) => true,
+            // Compressed payloads that are cut off or garbled
+            SaveError::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "Error accessing save files: {}", e),
+            SaveError::Corrupted(e) => write!(f, "Save data is corrupted: {}", e),
+            SaveError::UnsupportedVersion(version) => write!(
+                f,
+                "Save was made by a newer version of the game (format version {})",
+                version
+            ),
+            SaveError::InvalidWorldName(name) => write!(f, "Invalid world name: {:?}", name),
+            SaveError::WorldNotFound(name) => write!(f, "World does not exist: {}", name),
+            SaveError::WorldAlreadyExists(name) => write!(f, "World already exists: {}", name),
+            SaveError::NoValidBackup(name) => write!(f, "World has no valid backup: {}", name),
+            SaveError::MissingTileSet(name) => {
+                write!(
+                    f,
+                    "Tile set {} is missing or has no auto-tiling rules",
+                    name
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveError::Io(e) => Some(e),
+            SaveError::Corrupted(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for SaveError {
+    fn from(e: bincode::Error) -> Self {
+        SaveError::Corrupted(e)
+    }
+}
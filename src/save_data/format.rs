@@ -0,0 +1,126 @@
+use std::{borrow::Cow, io::Read, path::Path};
+
+use super::{compression, migration, SaveError, WorldMetadata, WorldSaveData};
+
+/// Magic bytes at the start of every versioned save file. Saves written before
+/// the format was versioned don't have a header at all.
+const SAVE_MAGIC: &[u8; 4] = b"TCSV";
+
+/// Version of the `WorldSaveData` layout written by this build of the game.
+/// Bump this, add a step to `migration` and save a fixture of the new version
+/// to `tests/fixtures/saves` whenever a saved struct changes.
+pub const CURRENT_SAVE_VERSION: u32 = 8;
+
+/// First format version whose payload is compressed.
+const FIRST_COMPRESSED_VERSION: u32 = 4;
+
+/// First format version with a metadata section between the header and the
+/// payload. The section is a `u32` length followed by the uncompressed
+/// `WorldMetadata`, so that it can be read without touching the payload.
+const FIRST_METADATA_VERSION: u32 = 5;
+
+/// Serializes world data and its metadata, compressing the world data,
+/// with a header holding the current format version.
+pub fn encode_world(
+    metadata: &WorldMetadata,
+    world_data: &WorldSaveData,
+) -> Result<Vec<u8>, SaveError> {
+    let metadata_bytes = bincode::serialize(metadata)?;
+
+    let mut bytes = write_header(SAVE_MAGIC, CURRENT_SAVE_VERSION);
+    bytes.extend_from_slice(&(metadata_bytes.len() as u32).to_le_bytes());
+    bytes.extend(metadata_bytes);
+    bytes.extend(compression::compress(&bincode::serialize(world_data)?)?);
+    Ok(bytes)
+}
+
+/// Deserializes world data of any supported format version, upgrading it
+/// to the current version. Saves from before metadata was added don't
+/// have any metadata.
+pub fn decode_world(bytes: &[u8]) -> Result<(Option<WorldMetadata>, WorldSaveData), SaveError> {
+    match read_header(bytes, SAVE_MAGIC) {
+        Some((version, payload)) => {
+            let (metadata, payload) = if version >= FIRST_METADATA_VERSION {
+                let (metadata_bytes, payload) = split_metadata(payload)?;
+                (Some(bincode::deserialize(metadata_bytes)?), payload)
+            } else {
+                (None, payload)
+            };
+
+            let payload = if version >= FIRST_COMPRESSED_VERSION {
+                compression::decompress(payload)?
+            } else {
+                Cow::Borrowed(payload)
+            };
+
+            Ok((metadata, migration::upgrade(version, &payload)?))
+        }
+        None => Ok((None, migration::upgrade_headerless(bytes)?)),
+    }
+}
+
+/// Reads only the header and metadata section of a save file.
+pub fn read_metadata(path: &Path) -> Result<Option<WorldMetadata>, SaveError> {
+    let mut file = std::fs::File::open(path)?;
+
+    // Magic bytes, format version and metadata length
+    let mut header = [0; 12];
+    file.read_exact(&mut header)?;
+
+    match read_header(&header, SAVE_MAGIC) {
+        Some((version, length_bytes)) if version >= FIRST_METADATA_VERSION => {
+            let mut metadata_bytes = vec![0; read_u32(length_bytes)? as usize];
+            file.read_exact(&mut metadata_bytes)?;
+            Ok(Some(bincode::deserialize(&metadata_bytes)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Splits the metadata section off the front of a payload.
+fn split_metadata(payload: &[u8]) -> Result<(&[u8], &[u8]), SaveError> {
+    let length = read_u32(payload)? as usize;
+    let payload = &payload[4..];
+
+    if payload.len() < length {
+        return Err(SaveError::Corrupted(Box::new(bincode::ErrorKind::Custom(
+            "Metadata section is cut off".to_owned(),
+        ))));
+    }
+
+    Ok(payload.split_at(length))
+}
+
+fn read_u32(bytes: &[u8]) -> Result<u32, SaveError> {
+    let bytes: [u8; 4] = bytes
+        .get(..4)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            SaveError::Corrupted(Box::new(bincode::ErrorKind::Custom(
+                "Save file is cut off".to_owned(),
+            )))
+        })?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Starts a file with the given magic bytes and format version.
+pub fn write_header(magic: &[u8; 4], version: u32) -> Vec<u8> {
+    let mut bytes = magic.to_vec();
+    bytes.extend_from_slice(&version.to_le_bytes());
+    bytes
+}
+
+/// Splits a file into its format version and payload,
+/// returning `None` if the file doesn't have the given header.
+pub fn read_header<'a>(bytes: &'a [u8], magic: &[u8; 4]) -> Option<(u32, &'a [u8])> {
+    let payload = bytes.strip_prefix(magic)?;
+
+    if payload.len() < 4 {
+        return None;
+    }
+
+    let (version_bytes, payload) = payload.split_at(4);
+    let version = read_u32(version_bytes).ok()?;
+    Some((version, payload))
+}
@@ -0,0 +1,256 @@
+//! Whole-world reading, writing and checking for tools that work on saves
+//! outside of the game, like the `terons-save` binary.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bevy::{math::IVec2, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    format, region, slots, storage, write_world_data, BlockData, ChunkData, LiquidData, SaveError,
+    WorldMetadata, WorldSaveData,
+};
+use crate::{
+    coords, item,
+    liquids::{self, LiquidKind},
+    tile_map::{self, TileSetDefinitions},
+};
+
+/// Everything stored for a world, with the blocks, walls and liquids of all
+/// chunks in a single list each.
+#[derive(Serialize, Deserialize)]
+pub struct WorldDump {
+    pub metadata: WorldMetadata,
+    pub world_data: WorldSaveData,
+    pub blocks: Vec<BlockData>,
+    /// Missing from dumps made before walls were added.
+    #[serde(default)]
+    pub walls: Vec<BlockData>,
+    /// Missing from dumps made before liquids were added.
+    #[serde(default)]
+    pub liquids: Vec<LiquidData>,
+}
+
+/// Counts of what a world contains.
+pub struct WorldStats {
+    pub chunk_count: usize,
+    pub blocks_per_tile_set: BTreeMap<String, usize>,
+    pub walls_per_tile_set: BTreeMap<String, usize>,
+    pub water_count: usize,
+    pub lava_count: usize,
+    pub items_per_name: BTreeMap<String, usize>,
+}
+
+/// Reads every save file of a world, without changing any of them.
+pub fn read_world(world_name: &str) -> Result<WorldDump, SaveError> {
+    slots::ensure_world_present(world_name)?;
+
+    let (metadata, mut world_data) =
+        storage::read_with_fallback(&slots::world_save_path(world_name), format::decode_world)?;
+
+    // Saves from before region files still hold their own blocks
+    let (mut blocks, mut walls, mut liquids) = (Vec::new(), Vec::new(), Vec::new());
+    match world_data.legacy_blocks.take() {
+        Some(legacy_blocks) => blocks.extend(legacy_blocks),
+        None => {
+            for (_, chunk) in region::load_all_chunks(world_name)? {
+                blocks.extend(chunk.blocks);
+                walls.extend(chunk.walls);
+                liquids.extend(chunk.liquids);
+            }
+        }
+    }
+    for tiles in [&mut blocks, &mut walls] {
+        tiles.sort_by(|a, b| {
+            (a.tile_pos.y, a.tile_pos.x, &a.tile_set).cmp(&(
+                b.tile_pos.y,
+                b.tile_pos.x,
+                &b.tile_set,
+            ))
+        });
+    }
+    liquids.sort_by_key(|liquid| (liquid.tile_pos.y, liquid.tile_pos.x));
+
+    Ok(WorldDump {
+        metadata: metadata.unwrap_or_else(|| WorldMetadata::new(world_name)),
+        world_data,
+        blocks,
+        walls,
+        liquids,
+    })
+}
+
+/// Replaces a world's save files with the contents of a dump, creating the
+/// world if it doesn't exist. The replaced files are kept as backups.
+pub fn write_world(world_name: &str, mut dump: WorldDump) -> Result<(), SaveError> {
+    slots::validate_world_name(world_name)?;
+    std::fs::create_dir_all(slots::world_dir(world_name))?;
+
+    // Remove chunks that aren't part of the dump
+    let mut chunks: HashMap<IVec2, Option<ChunkData>> = region::load_all_chunks(world_name)?
+        .into_iter()
+        .map(|(chunk_pos, _)| (chunk_pos, None))
+        .collect();
+    chunks.extend(
+        region::group_into_chunks(dump.blocks, dump.walls, dump.liquids)
+            .into_iter()
+            .map(|(chunk_pos, chunk)| (chunk_pos, Some(chunk))),
+    );
+
+    region::save_chunks(world_name, chunks)?;
+
+    // The dump might come from a world with a different name
+    dump.metadata.name = world_name.to_owned();
+    write_world_data(world_name, &dump.metadata, &dump.world_data)
+}
+
+/// Looks for data the game can't make sense of: blocks and walls of unknown
+/// tile sets or tiles, liquids with impossible levels, unknown items and
+/// blocks, walls or liquids sharing a tile. Returns a description of every
+/// problem found.
+pub fn validate(dump: &WorldDump) -> Result<Vec<String>, SaveError> {
+    let item_names = item::item_names()?;
+    let definitions = tile_map::load_tile_set_definitions()?;
+    let mut problems = Vec::new();
+
+    validate_tiles("Block", &dump.blocks, &definitions, &mut problems);
+    validate_tiles("Wall", &dump.walls, &definitions, &mut problems);
+
+    let mut liquid_positions = BTreeSet::new();
+    for liquid in &dump.liquids {
+        let position = (liquid.tile_pos.x, liquid.tile_pos.y);
+
+        if !(1..=liquids::MAX_LEVEL).contains(&liquid.level) {
+            problems.push(format!(
+                "Liquid at {:?} has level {}, which isn't between 1 and {}",
+                position,
+                liquid.level,
+                liquids::MAX_LEVEL
+            ));
+        }
+
+        if !liquid_positions.insert(position) {
+            problems.push(format!("More than one liquid at {:?}", position));
+        }
+    }
+
+    for item in &dump.world_data.items {
+        if !item_names.contains(&item.item_name) {
+            problems.push(format!(
+                "Item at {:?} is unknown item {}",
+                (item.position.x, item.position.y),
+                item.item_name
+            ));
+        }
+    }
+
+    if let Some(player) = &dump.world_data.player {
+        for (item_name, _) in player.inventory_slots.iter().flatten() {
+            if !item_names.contains(item_name) {
+                problems.push(format!("Player inventory holds unknown item {}", item_name));
+            }
+        }
+
+        if player.inventory_slots.len() > player.max_slots {
+            problems.push(format!(
+                "Player inventory uses {} slots but only has {}",
+                player.inventory_slots.len(),
+                player.max_slots
+            ));
+        }
+
+        if player.selected_slot >= player.max_slots {
+            problems.push(format!(
+                "Player has slot {} selected but only has {} slots",
+                player.selected_slot, player.max_slots
+            ));
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Checks the tiles of one layer, naming them `kind` in the problems found.
+fn validate_tiles(
+    kind: &str,
+    tiles: &[BlockData],
+    definitions: &TileSetDefinitions,
+    problems: &mut Vec<String>,
+) {
+    let mut tile_positions = BTreeSet::new();
+    for tile in tiles {
+        let position = (tile.tile_pos.x, tile.tile_pos.y);
+
+        match definitions.get(&tile.tile_set) {
+            Some(definition) if tile.tile_index >= definition.tile_count() => {
+                problems.push(format!(
+                    "{} at {:?} uses tile {} of tile set {}, which only has {} tiles",
+                    kind,
+                    position,
+                    tile.tile_index,
+                    tile.tile_set,
+                    definition.tile_count()
+                ));
+            }
+            Some(definition) if tile.material() >= definition.tile_count() => {
+                problems.push(format!(
+                    "{} at {:?} is made of tile {} of tile set {}, which only has {} tiles",
+                    kind,
+                    position,
+                    tile.material(),
+                    tile.tile_set,
+                    definition.tile_count()
+                ));
+            }
+            Some(_) => (),
+            None => problems.push(format!(
+                "{} at {:?} uses unknown tile set {}",
+                kind, position, tile.tile_set
+            )),
+        }
+
+        if !tile_positions.insert(position) {
+            problems.push(format!(
+                "More than one {} at {:?}",
+                kind.to_lowercase(),
+                position
+            ));
+        }
+    }
+}
+
+pub fn stats(dump: &WorldDump) -> WorldStats {
+    let mut chunks = BTreeSet::new();
+    let mut count_tiles = |tiles: &[BlockData]| {
+        let mut tiles_per_tile_set = BTreeMap::new();
+        for tile in tiles {
+            let tile_pos = IVec2::new(tile.tile_pos.x, tile.tile_pos.y);
+            chunks.insert(coords::tile_to_chunk(tile_pos).to_array());
+            *tiles_per_tile_set.entry(tile.tile_set.clone()).or_default() += 1;
+        }
+        tiles_per_tile_set
+    };
+    let blocks_per_tile_set = count_tiles(&dump.blocks);
+    let walls_per_tile_set = count_tiles(&dump.walls);
+
+    let liquid_count = |kind| {
+        dump.liquids
+            .iter()
+            .filter(|liquid| liquid.kind == kind)
+            .count()
+    };
+
+    let mut items_per_name = BTreeMap::new();
+    for item in &dump.world_data.items {
+        *items_per_name.entry(item.item_name.clone()).or_default() += 1;
+    }
+
+    WorldStats {
+        chunk_count: chunks.len(),
+        blocks_per_tile_set,
+        walls_per_tile_set,
+        water_count: liquid_count(LiquidKind::Water),
+        lava_count: liquid_count(LiquidKind::Lava),
+        items_per_name,
+    }
+}
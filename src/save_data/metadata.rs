@@ -0,0 +1,52 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Information about a world that can be read without loading the world
+/// itself, for showing it in a world list. Stored near the start of the
+/// world's save file.
+///
+/// While a world is loaded, its metadata is also available as a resource.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WorldMetadata {
+    pub name: String,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    /// Seconds since the Unix epoch.
+    pub last_played: u64,
+    /// Seconds spent in `GameState::Game`.
+    pub playtime: f64,
+    pub seed: u64,
+    /// Version of the game that last saved the world.
+    pub game_version: String,
+}
+
+impl WorldMetadata {
+    /// Metadata for a world created now. Also used for saves from before
+    /// metadata was added, whose real creation time is unknown.
+    pub fn new(name: &str) -> Self {
+        let now = unix_time_now();
+
+        Self {
+            name: name.to_owned(),
+            created: now,
+            last_played: now,
+            playtime: 0.,
+            seed: 0,
+            game_version: env!("CARGO_PKG_VERSION").to_owned(),
+        }
+    }
+}
+
+pub fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// System that counts the time spent playing the loaded world.
+pub fn playtime_system(time: Res<Time>, mut metadata: ResMut<WorldMetadata>) {
+    metadata.playtime += time.delta_seconds_f64();
+}
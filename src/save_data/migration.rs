@@ -0,0 +1,502 @@
+//! Upgrades world saves written by older versions of the game.
+//!
+//! Every format version whose layout differs from the current one keeps a frozen
+//! copy of its structs in a module named after it, plus a step that converts it
+//! into the next version. Loading an old save runs every step up to
+//! `CURRENT_SAVE_VERSION`. Only the last step converts into the current
+//! structs, so that changing them can't change how old saves are read.
+
+use super::{
+    format::CURRENT_SAVE_VERSION, BlockData, ItemData, PlayerSaveData, PositionData, SaveError,
+    VectorData, WorldSaveData,
+};
+use crate::{player, world_clock};
+
+/// Decodes a payload of the given format version and upgrades it to the current one.
+pub fn upgrade(version: u32, payload: &[u8]) -> Result<WorldSaveData, SaveError> {
+    match version {
+        1 => Ok(v7_to_v8(v6_to_v7(v5_to_v6(v2_to_v3(v1_to_v2(
+            bincode::deserialize(payload)?,
+        )))))),
+        2 => Ok(v7_to_v8(v6_to_v7(v5_to_v6(v2_to_v3(
+            bincode::deserialize(payload)?,
+        ))))),
+        // Version 4 compresses the payload and version 5 adds a metadata
+        // section, both of which are dealt with before upgrading
+        3..=5 => Ok(v7_to_v8(v6_to_v7(v5_to_v6(bincode::deserialize(payload)?)))),
+        6 => Ok(v7_to_v8(v6_to_v7(bincode::deserialize(payload)?))),
+        7 => Ok(v7_to_v8(bincode::deserialize(payload)?)),
+        CURRENT_SAVE_VERSION => Ok(bincode::deserialize(payload)?),
+        _ => Err(SaveError::UnsupportedVersion(version)),
+    }
+}
+
+/// Saves from before the header was introduced are either version 1 or version 2.
+/// Version 2 only appends a field to version 1, so a version 1 save always fails
+/// to decode as version 2 and the versions can be told apart by trying both.
+pub fn upgrade_headerless(bytes: &[u8]) -> Result<WorldSaveData, SaveError> {
+    upgrade(2, bytes).or_else(|_| upgrade(1, bytes))
+}
+
+/// Version 1: the original format, without any player data.
+mod v1 {
+    use bevy::utils::HashSet;
+    use serde::Deserialize;
+
+    use super::v5::ItemData;
+
+    #[derive(Deserialize)]
+    pub struct WorldSaveData {
+        pub player_spawn: PositionData,
+        pub blocks: HashSet<BlockData>,
+        pub items: HashSet<ItemData>,
+    }
+
+    #[derive(Deserialize, PartialEq, Eq, Hash)]
+    pub struct BlockData {
+        pub tile_set: String,
+        pub tile_index: usize,
+        pub tile_pos: PositionData,
+    }
+
+    #[derive(Deserialize, PartialEq, Eq, Hash)]
+    pub struct PositionData {
+        pub x: i32,
+        pub y: i32,
+    }
+}
+
+/// Version 2: blocks are still stored in the world save instead of region files.
+mod v2 {
+    use bevy::utils::HashSet;
+    use serde::Deserialize;
+
+    use super::{
+        v1::{BlockData, PositionData},
+        v5::{ItemData, PlayerSaveData},
+    };
+
+    #[derive(Deserialize)]
+    pub struct WorldSaveData {
+        pub player_spawn: PositionData,
+        pub blocks: HashSet<BlockData>,
+        pub items: HashSet<ItemData>,
+        pub player: Option<PlayerSaveData>,
+    }
+}
+
+/// Versions 3 to 5: item and player positions are rounded to whole
+/// pixels, and neither velocities nor item state are saved.
+mod v5 {
+    use bevy::utils::HashSet;
+    use serde::Deserialize;
+
+    use super::v1::{BlockData, PositionData};
+
+    #[derive(Deserialize)]
+    pub struct WorldSaveData {
+        pub player_spawn: PositionData,
+        pub items: HashSet<ItemData>,
+        pub player: Option<PlayerSaveData>,
+
+        #[serde(skip)]
+        pub legacy_blocks: Option<HashSet<BlockData>>,
+    }
+
+    #[derive(Deserialize, PartialEq, Eq, Hash)]
+    pub struct ItemData {
+        pub item_name: String,
+        pub position: PositionData,
+    }
+
+    #[derive(Deserialize)]
+    pub struct PlayerSaveData {
+        pub position: PositionData,
+        pub facing_left: bool,
+        pub inventory_slots: Vec<(String, usize)>,
+        pub max_slots: usize,
+    }
+}
+
+/// Version 6: the world's clock isn't saved.
+mod v6 {
+    use bevy::utils::HashSet;
+    use serde::Deserialize;
+
+    use super::{
+        v1::{BlockData, PositionData},
+        v7::{ItemData, PlayerSaveData},
+    };
+
+    #[derive(Deserialize)]
+    pub struct WorldSaveData {
+        pub player_spawn: PositionData,
+        pub items: Vec<ItemData>,
+        pub player: Option<PlayerSaveData>,
+
+        #[serde(skip)]
+        pub legacy_blocks: Option<HashSet<BlockData>>,
+    }
+}
+
+/// Version 7: the player's health and selected slot aren't saved, and
+/// inventory slots are packed together without any empty ones.
+mod v7 {
+    use bevy::utils::HashSet;
+    use serde::Deserialize;
+
+    use super::v1::{BlockData, PositionData};
+
+    #[derive(Deserialize)]
+    pub struct WorldSaveData {
+        pub player_spawn: PositionData,
+        pub items: Vec<ItemData>,
+        pub player: Option<PlayerSaveData>,
+        pub world_time: f64,
+
+        #[serde(skip)]
+        pub legacy_blocks: Option<HashSet<BlockData>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ItemData {
+        pub item_name: String,
+        pub position: VectorData,
+        pub rotation: f32,
+        pub velocity: VectorData,
+        pub angular_velocity: f32,
+        pub picked_up: bool,
+        pub attractor_strength: Option<f32>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct PlayerSaveData {
+        pub position: VectorData,
+        pub velocity: VectorData,
+        pub facing_left: bool,
+        pub inventory_slots: Vec<(String, usize)>,
+        pub max_slots: usize,
+    }
+
+    #[derive(Deserialize, Default)]
+    pub struct VectorData {
+        pub x: f32,
+        pub y: f32,
+    }
+}
+
+fn v1_to_v2(world_data: v1::WorldSaveData) -> v2::WorldSaveData {
+    v2::WorldSaveData {
+        player_spawn: world_data.player_spawn,
+        blocks: world_data.blocks,
+        items: world_data.items,
+        player: None,
+    }
+}
+
+fn v2_to_v3(world_data: v2::WorldSaveData) -> v5::WorldSaveData {
+    v5::WorldSaveData {
+        player_spawn: world_data.player_spawn,
+        items: world_data.items,
+        player: world_data.player,
+        legacy_blocks: Some(world_data.blocks),
+    }
+}
+
+fn v5_to_v6(world_data: v5::WorldSaveData) -> v6::WorldSaveData {
+    let items = world_data
+        .items
+        .into_iter()
+        .map(|item| v7::ItemData {
+            item_name: item.item_name,
+            position: v7::VectorData {
+                x: item.position.x as f32,
+                y: item.position.y as f32,
+            },
+            rotation: 0.,
+            velocity: v7::VectorData::default(),
+            angular_velocity: 0.,
+            picked_up: false,
+            attractor_strength: None,
+        })
+        .collect();
+
+    let player = world_data.player.map(|player| v7::PlayerSaveData {
+        position: v7::VectorData {
+            x: player.position.x as f32,
+            y: player.position.y as f32,
+        },
+        velocity: v7::VectorData::default(),
+        facing_left: player.facing_left,
+        inventory_slots: player.inventory_slots,
+        max_slots: player.max_slots,
+    });
+
+    v6::WorldSaveData {
+        player_spawn: world_data.player_spawn,
+        items,
+        player,
+        legacy_blocks: world_data.legacy_blocks,
+    }
+}
+
+/// Worlds from before the clock start the day over.
+fn v6_to_v7(world_data: v6::WorldSaveData) -> v7::WorldSaveData {
+    v7::WorldSaveData {
+        player_spawn: world_data.player_spawn,
+        items: world_data.items,
+        player: world_data.player,
+        world_time: world_clock::NEW_WORLD_TIME,
+        legacy_blocks: world_data.legacy_blocks,
+    }
+}
+
+/// Players from before health was saved are fully healed, and hold their
+/// first slot. Blocks from before materials were saved are made of the tile
+/// they are drawn with.
+fn v7_to_v8(world_data: v7::WorldSaveData) -> WorldSaveData {
+    let items = world_data
+        .items
+        .into_iter()
+        .map(|item| ItemData {
+            item_name: item.item_name,
+            position: upgrade_vector(item.position),
+            rotation: item.rotation,
+            velocity: upgrade_vector(item.velocity),
+            angular_velocity: item.angular_velocity,
+            picked_up: item.picked_up,
+            attractor_strength: item.attractor_strength,
+        })
+        .collect();
+
+    let player = world_data.player.map(|player| PlayerSaveData {
+        position: upgrade_vector(player.position),
+        velocity: upgrade_vector(player.velocity),
+        facing_left: player.facing_left,
+        inventory_slots: player.inventory_slots.into_iter().map(Some).collect(),
+        max_slots: player.max_slots,
+        selected_slot: 0,
+        health: player::PLAYER_MAX_HEALTH,
+    });
+
+    let legacy_blocks = world_data.legacy_blocks.map(|blocks| {
+        blocks
+            .into_iter()
+            .map(|block| BlockData {
+                tile_set: block.tile_set,
+                tile_index: block.tile_index,
+                material: None,
+                tile_pos: upgrade_position(block.tile_pos),
+            })
+            .collect()
+    });
+
+    WorldSaveData {
+        player_spawn: upgrade_position(world_data.player_spawn),
+        items,
+        player,
+        world_time: world_data.world_time,
+        legacy_blocks,
+    }
+}
+
+fn upgrade_position(position: v1::PositionData) -> PositionData {
+    PositionData {
+        x: position.x,
+        y: position.y,
+    }
+}
+
+fn upgrade_vector(vector: v7::VectorData) -> VectorData {
+    VectorData {
+        x: vector.x,
+        y: vector.y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save_data::format;
+
+    /// The same world saved by every format version, with the format version
+    /// it was saved with. Saves from before the header are version 1 or 2.
+    /// Add a fixture whenever `CURRENT_SAVE_VERSION` is bumped.
+    const FIXTURES: [(&str, u32, &[u8]); 9] = [
+        (
+            "v1",
+            1,
+            include_bytes!("../../tests/fixtures/saves/v1.save"),
+        ),
+        (
+            "v2_headerless",
+            2,
+            include_bytes!("../../tests/fixtures/saves/v2_headerless.save"),
+        ),
+        (
+            "v2",
+            2,
+            include_bytes!("../../tests/fixtures/saves/v2.save"),
+        ),
+        (
+            "v3",
+            3,
+            include_bytes!("../../tests/fixtures/saves/v3.save"),
+        ),
+        (
+            "v4",
+            4,
+            include_bytes!("../../tests/fixtures/saves/v4.save"),
+        ),
+        (
+            "v5",
+            5,
+            include_bytes!("../../tests/fixtures/saves/v5.save"),
+        ),
+        (
+            "v6",
+            6,
+            include_bytes!("../../tests/fixtures/saves/v6.save"),
+        ),
+        (
+            "v7",
+            7,
+            include_bytes!("../../tests/fixtures/saves/v7.save"),
+        ),
+        (
+            "v8",
+            8,
+            include_bytes!("../../tests/fixtures/saves/v8.save"),
+        ),
+    ];
+
+    fn vector(vector: VectorData) -> (f32, f32) {
+        (vector.x, vector.y)
+    }
+
+    #[test]
+    fn every_version_upgrades_to_current() {
+        for (name, version, bytes) in FIXTURES {
+            let (metadata, world_data) = format::decode_world(bytes)
+                .unwrap_or_else(|e| panic!("Fixture {} failed to decode: {}", name, e));
+
+            assert_eq!(
+                (world_data.player_spawn.x, world_data.player_spawn.y),
+                (16, 48),
+                "{}",
+                name
+            );
+
+            // Versions before 6 rounded positions to whole pixels
+            let full_precision = version >= 6;
+
+            assert_eq!(world_data.items.len(), 1, "{}", name);
+            let item = &world_data.items[0];
+            assert_eq!(item.item_name, "dirt", "{}", name);
+            if full_precision {
+                assert_eq!(vector(item.position), (32.25, 64.5), "{}", name);
+                assert_eq!(vector(item.velocity), (1.5, -2.5), "{}", name);
+                assert_eq!(item.rotation, 0.25, "{}", name);
+                assert_eq!(item.angular_velocity, 0.5, "{}", name);
+            } else {
+                assert_eq!(vector(item.position), (32., 64.), "{}", name);
+                assert_eq!(vector(item.velocity), (0., 0.), "{}", name);
+                assert_eq!(item.rotation, 0., "{}", name);
+                assert_eq!(item.angular_velocity, 0., "{}", name);
+            }
+            assert!(!item.picked_up, "{}", name);
+            assert_eq!(item.attractor_strength, None, "{}", name);
+
+            let expected_time = if version >= 7 {
+                500.
+            } else {
+                world_clock::NEW_WORLD_TIME
+            };
+            assert_eq!(world_data.world_time, expected_time, "{}", name);
+
+            // Version 1 has no player
+            match &world_data.player {
+                Some(player) => {
+                    assert!(version >= 2, "{}", name);
+                    if full_precision {
+                        assert_eq!(vector(player.position), (100.5, 200.25), "{}", name);
+                        assert_eq!(vector(player.velocity), (1.5, -2.5), "{}", name);
+                    } else {
+                        assert_eq!(vector(player.position), (100., 200.), "{}", name);
+                        assert_eq!(vector(player.velocity), (0., 0.), "{}", name);
+                    }
+                    assert!(player.facing_left, "{}", name);
+                    assert_eq!(
+                        player.inventory_slots,
+                        [Some(("dirt".to_owned(), 3)), Some(("stone".to_owned(), 1))],
+                        "{}",
+                        name
+                    );
+                    assert_eq!(player.max_slots, 9, "{}", name);
+
+                    let (selected_slot, health) = if version >= 8 {
+                        (1, 42.)
+                    } else {
+                        (0, player::PLAYER_MAX_HEALTH)
+                    };
+                    assert_eq!(player.selected_slot, selected_slot, "{}", name);
+                    assert_eq!(player.health, health, "{}", name);
+                }
+                None => assert_eq!(version, 1, "{}", name),
+            }
+
+            // Versions 1 and 2 store their blocks in the world save
+            match &world_data.legacy_blocks {
+                Some(legacy_blocks) => {
+                    assert!(version <= 2, "{}", name);
+                    let mut blocks: Vec<_> = legacy_blocks
+                        .iter()
+                        .map(|block| {
+                            (
+                                block.tile_set.as_str(),
+                                block.tile_index,
+                                block.material(),
+                                (block.tile_pos.x, block.tile_pos.y),
+                            )
+                        })
+                        .collect();
+                    blocks.sort();
+                    assert_eq!(
+                        blocks,
+                        [
+                            ("jungle_floor", 3, 3, (1, 2)),
+                            ("jungle_floor", 12, 12, (-4, -5))
+                        ],
+                        "{}",
+                        name
+                    );
+                }
+                None => assert!(version >= 3, "{}", name),
+            }
+
+            // Version 5 adds metadata
+            match metadata {
+                Some(metadata) => {
+                    assert!(version >= 5, "{}", name);
+                    assert_eq!(metadata.name, "fixture", "{}", name);
+                    assert_eq!(metadata.created, 1_600_000_000, "{}", name);
+                    assert_eq!(metadata.last_played, 1_600_000_100, "{}", name);
+                    assert_eq!(metadata.playtime, 42.5, "{}", name);
+                    assert_eq!(metadata.seed, 7, "{}", name);
+                    assert_eq!(metadata.game_version, "0.1.0", "{}", name);
+                }
+                None => assert!(version < 5, "{}", name),
+            }
+        }
+    }
+
+    #[test]
+    fn upgraded_saves_decode_as_current_version() {
+        for (name, _, bytes) in FIXTURES {
+            let (_, world_data) = format::decode_world(bytes).unwrap();
+            let payload = bincode::serialize(&world_data).unwrap();
+
+            assert!(upgrade(CURRENT_SAVE_VERSION, &payload).is_ok(), "{}", name);
+        }
+    }
+}
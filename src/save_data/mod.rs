@@ -0,0 +1,631 @@
+use bevy::{
+    app::AppExit,
+    core::FixedTimestep,
+    ecs::system::SystemParam,
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier2d::prelude::*;
+use futures_lite::future;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::{Frozen, Health, Inventory, Item, Player, PlayerAttractor, SaveIndicator},
+    coords, in_game,
+    item::SpawnItemEvent,
+    liquids::{self, LiquidKind},
+    player::{self, SpawnPlayerEvent},
+    tile_map::{self, TileLayer, TileProperties, TileSetDefinitions},
+    world_clock::{self, WorldClock},
+    world_gen::{self, WorldGenerator, TERRAIN_TILE_SET},
+    GameState, UIAssets,
+};
+
+use region::ChunkData;
+
+pub use error::SaveError;
+pub use metadata::WorldMetadata;
+pub use slots::ActiveWorld;
+pub use world_store::WorldStore;
+
+mod compression;
+mod error;
+mod format;
+pub mod inspect;
+mod metadata;
+mod migration;
+mod region;
+pub mod slots;
+mod storage;
+mod world_store;
+
+const SAVE_DATA_PATH: &str = "world_saves";
+
+/// Sent when the active world can't be loaded, after the game has
+/// been sent back to `GameState::MainMenu`.
+pub struct WorldLoadFailedEvent {
+    pub world_name: String,
+    pub error: SaveError,
+}
+
+pub struct SaveDataPlugin;
+
+impl Plugin for SaveDataPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<WorldLoadFailedEvent>()
+            .insert_resource(ActiveWorld::from_args())
+            .init_resource::<PendingSave>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Game)
+                    .with_system(save_data_setup_system)
+                    .with_system(save_indicator_setup_system),
+            )
+            .add_system(pending_save_system)
+            // Runs in the last stage so that exits requested anywhere
+            // during the frame are seen before the app shuts down.
+            .add_system_to_stage(CoreStage::Last, app_exit_save_system)
+            .add_system_set(
+                SystemSet::on_update(GameState::Game).with_system(metadata::playtime_system),
+            )
+            .add_system_set(
+                // Save world data every 5 minutes
+                SystemSet::new()
+                    .with_run_criteria(FixedTimestep::step(300.).chain(in_game))
+                    .with_system(periodic_save_system),
+            );
+    }
+}
+
+/// Everything in a world except its blocks, walls and liquids, which are
+/// stored in region files.
+#[derive(Serialize, Deserialize)]
+pub struct WorldSaveData {
+    pub player_spawn: PositionData,
+    pub items: Vec<ItemData>,
+    /// `None` until the player has been saved in this world for the first time.
+    pub player: Option<PlayerSaveData>,
+    /// Time on the world's `WorldClock`. Missing from dumps made before
+    /// the clock was added.
+    #[serde(default = "new_world_time")]
+    pub world_time: f64,
+
+    /// Blocks of a save from before blocks were moved into region files.
+    /// They are written into region files when the world is loaded.
+    #[serde(skip)]
+    pub legacy_blocks: Option<HashSet<BlockData>>,
+}
+
+fn new_world_time() -> f64 {
+    world_clock::NEW_WORLD_TIME
+}
+
+fn full_health() -> f32 {
+    player::PLAYER_MAX_HEALTH
+}
+
+/// Generates a new world from a seed, returning its world data and chunks.
+fn generate_world(seed: u64) -> Result<(WorldSaveData, HashMap<IVec2, ChunkData>), SaveError> {
+    let terrain_rules = tile_map::load_tile_set_definitions()?
+        .get(TERRAIN_TILE_SET)
+        .and_then(|definition| definition.auto_tile.clone())
+        .ok_or_else(|| SaveError::MissingTileSet(TERRAIN_TILE_SET.to_owned()))?;
+    let generator = WorldGenerator::new(seed, terrain_rules);
+
+    let mut blocks = Vec::new();
+    let mut walls = Vec::new();
+    for generated in generator.generate() {
+        let tile = BlockData {
+            tile_set: generated.tile_set.to_owned(),
+            tile_index: generated.tile_index,
+            material: Some(generated.material).filter(|material| *material != generated.tile_index),
+            tile_pos: PositionData {
+                x: generated.tile_pos.x,
+                y: generated.tile_pos.y,
+            },
+        };
+
+        match generated.layer {
+            TileLayer::Block => blocks.push(tile),
+            TileLayer::Wall => walls.push(tile),
+        }
+    }
+    let liquids = generator
+        .generate_liquids()
+        .into_iter()
+        .map(|(tile_pos, kind)| LiquidData {
+            kind,
+            level: liquids::MAX_LEVEL,
+            tile_pos: PositionData {
+                x: tile_pos.x,
+                y: tile_pos.y,
+            },
+        });
+    let chunks = region::group_into_chunks(blocks, walls, liquids);
+
+    let spawn_point = coords::tile_to_world(generator.spawn_point());
+
+    // Start the player off with a pickaxe next to them
+    let items = vec![ItemData {
+        item_name: "pickaxe".to_owned(),
+        position: (spawn_point + Vec2::new(2. * coords::TILE_SIZE, 0.)).into(),
+        rotation: 0.,
+        velocity: VectorData::default(),
+        angular_velocity: 0.,
+        picked_up: false,
+        attractor_strength: None,
+    }];
+
+    let world_data = WorldSaveData {
+        player_spawn: PositionData {
+            x: spawn_point.x as i32,
+            y: spawn_point.y as i32,
+        },
+        items,
+        player: None,
+        world_time: world_clock::NEW_WORLD_TIME,
+        legacy_blocks: None,
+    };
+
+    Ok((world_data, chunks))
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct BlockData {
+    pub tile_set: String,
+    pub tile_index: usize,
+    /// Tile the block was placed as, if auto-tiling picked a different
+    /// `tile_index` for it. Missing from dumps made before materials were saved.
+    #[serde(default)]
+    pub material: Option<usize>,
+    pub tile_pos: PositionData,
+}
+
+impl BlockData {
+    /// Tile the block was placed as, see `Tile::material`.
+    pub fn material(&self) -> usize {
+        self.material.unwrap_or(self.tile_index)
+    }
+
+    /// Properties of the block's material, or `None` if its tile set isn't defined.
+    pub fn properties<'a>(
+        &self,
+        definitions: &'a TileSetDefinitions,
+    ) -> Option<&'a TileProperties> {
+        definitions
+            .get(&self.tile_set)
+            .map(|definition| definition.properties(self.material()))
+    }
+}
+
+/// Liquid filling a tile up to `level`, out of `liquids::MAX_LEVEL`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct LiquidData {
+    pub kind: LiquidKind,
+    pub level: u8,
+    pub tile_pos: PositionData,
+}
+
+/// An item lying in the world, with enough of its physics state
+/// to carry on moving the same way after loading.
+#[derive(Serialize, Deserialize)]
+pub struct ItemData {
+    pub item_name: String,
+    pub position: VectorData,
+    /// Rotation around the z axis, in radians.
+    pub rotation: f32,
+    pub velocity: VectorData,
+    pub angular_velocity: f32,
+    pub picked_up: bool,
+    /// Strength of the item's `PlayerAttractor`, if it is being pulled towards the player.
+    pub attractor_strength: Option<f32>,
+}
+
+/// Everything about the player that should survive a restart.
+/// New player stats should be added here.
+#[derive(Serialize, Deserialize)]
+pub struct PlayerSaveData {
+    pub position: VectorData,
+    pub velocity: VectorData,
+    pub facing_left: bool,
+    /// Emptied slots are kept as `None`, so that items stay in their slots.
+    pub inventory_slots: Vec<Option<(String, usize)>>,
+    pub max_slots: usize,
+    /// Missing from dumps made before the selected slot and health were saved.
+    #[serde(default)]
+    pub selected_slot: usize,
+    #[serde(default = "full_health")]
+    pub health: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct PositionData {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct VectorData {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl From<Vec2> for VectorData {
+    fn from(vector: Vec2) -> Self {
+        Self {
+            x: vector.x,
+            y: vector.y,
+        }
+    }
+}
+
+impl From<VectorData> for Vec2 {
+    fn from(vector: VectorData) -> Self {
+        Vec2::new(vector.x, vector.y)
+    }
+}
+
+/// Save currently being written in the background, if any.
+#[derive(Default)]
+struct PendingSave(Option<Task<Result<(), SaveError>>>);
+
+/// Everything a save writes to disk, copied out of the ECS.
+struct WorldSnapshot {
+    metadata: WorldMetadata,
+    world_data: WorldSaveData,
+    /// Chunks that changed since the last save, `None` for chunks that are now empty.
+    dirty_chunks: HashMap<IVec2, Option<ChunkData>>,
+}
+
+/// Position new players are spawned at in the loaded world.
+pub struct WorldSpawn(pub Vec3);
+
+/// System that loads/generates the game save data. If the world
+/// can't be loaded, the game goes back to the main menu instead.
+fn save_data_setup_system(
+    mut commands: Commands,
+    active_world: Res<ActiveWorld>,
+    mut game_state: ResMut<State<GameState>>,
+    mut load_failed_events: EventWriter<WorldLoadFailedEvent>,
+    mut item_events: EventWriter<SpawnItemEvent>,
+    mut player_events: EventWriter<SpawnPlayerEvent>,
+) {
+    let (mut metadata, world_data) = match load_world_data(&active_world.0) {
+        Ok(world) => world,
+        Err(error) => {
+            eprintln!("Error loading world {}: {}", active_world.0, error);
+
+            if let Err(e) = game_state.set(GameState::MainMenu) {
+                eprintln!("Something went wrong while setting MainMenu state: {}", e);
+            }
+
+            load_failed_events.send(WorldLoadFailedEvent {
+                world_name: active_world.0.clone(),
+                error,
+            });
+            return;
+        }
+    };
+
+    metadata.last_played = metadata::unix_time_now();
+    commands.insert_resource(metadata);
+
+    // Blocks are spawned by chunk streaming once the camera is near them
+    commands.insert_resource(WorldStore::new(&active_world.0));
+    commands.insert_resource(WorldClock::new(world_data.world_time));
+
+    // Spawn items
+    item_events.send_batch(world_data.items.iter().map(|item_data| SpawnItemEvent {
+        item_name: item_data.item_name.clone(),
+        position: item_data.position.into(),
+        rotation: item_data.rotation,
+        velocity: Velocity {
+            linvel: item_data.velocity.into(),
+            angvel: item_data.angular_velocity,
+        },
+        picked_up: item_data.picked_up,
+        attractor_strength: item_data.attractor_strength,
+    }));
+
+    // Spawn player
+    let world_spawn = Vec3::new(
+        world_data.player_spawn.x as f32,
+        world_data.player_spawn.y as f32,
+        0.0,
+    );
+    commands.insert_resource(WorldSpawn(world_spawn));
+
+    let player_event = match world_data.player {
+        Some(player_data) => SpawnPlayerEvent {
+            position: Vec2::from(player_data.position).extend(0.0),
+            velocity: player_data.velocity.into(),
+            facing_left: player_data.facing_left,
+            health: player_data.health,
+            inventory: Inventory {
+                slots: player_data.inventory_slots,
+                max_slots: player_data.max_slots,
+                selected_slot: player_data.selected_slot,
+            },
+        },
+        None => SpawnPlayerEvent {
+            position: world_spawn,
+            ..Default::default()
+        },
+    };
+    player_events.send(player_event);
+}
+
+/// System that saves the world data every 5 minutes. The world is
+/// snapshotted here, but serialized and written in the background.
+fn periodic_save_system(
+    io_pool: Res<IoTaskPool>,
+    mut pending_save: ResMut<PendingSave>,
+    active_world: Res<ActiveWorld>,
+    mut saved_world: SavedWorld,
+) {
+    if pending_save.0.is_some() {
+        eprintln!("Previous save is still in progress, skipping periodic save");
+        return;
+    }
+
+    if let Some(snapshot) = saved_world.snapshot() {
+        pending_save.0 = Some(spawn_save_task(&io_pool, active_world.0.clone(), snapshot));
+    }
+}
+
+/// System that save the world data when the game is closed. Unlike the
+/// periodic save, this waits for the save to finish so that the process
+/// doesn't end halfway through writing it.
+fn app_exit_save_system(
+    app_exit_events: EventReader<AppExit>,
+    io_pool: Res<IoTaskPool>,
+    mut pending_save: ResMut<PendingSave>,
+    active_world: Res<ActiveWorld>,
+    mut saved_world: SavedWorld,
+) {
+    if app_exit_events.is_empty() {
+        return;
+    }
+
+    // Let the periodic save finish first so it can't overwrite this one
+    if let Some(task) = pending_save.0.take() {
+        if let Err(e) = future::block_on(task) {
+            eprintln!("Error writing world data: {}", e);
+        }
+    }
+
+    if let Some(snapshot) = saved_world.snapshot() {
+        let task = spawn_save_task(&io_pool, active_world.0.clone(), snapshot);
+
+        if let Err(e) = future::block_on(task) {
+            eprintln!("Error writing world data: {}", e);
+        }
+    }
+}
+
+/// System that checks on the save running in the background, and shows
+/// the save indicator while it is in progress.
+fn pending_save_system(
+    mut pending_save: ResMut<PendingSave>,
+    world_store: Option<ResMut<WorldStore>>,
+    mut indicator_query: Query<&mut Visibility, With<SaveIndicator>>,
+) {
+    if let Some(task) = &mut pending_save.0 {
+        if let Some(result) = future::block_on(future::poll_once(task)) {
+            if let Err(e) = result {
+                eprintln!("Error writing world data: {}", e);
+
+                // Some chunks might not have been written, so rewrite all of them next time
+                if let Some(mut world_store) = world_store {
+                    world_store.mark_all_dirty();
+                }
+            }
+
+            pending_save.0 = None;
+        }
+    }
+
+    for mut visibility in indicator_query.iter_mut() {
+        visibility.is_visible = pending_save.0.is_some();
+    }
+}
+
+/// System that spawns the (hidden) "Saving..." indicator.
+fn save_indicator_setup_system(
+    mut commands: Commands,
+    ui_assets: Res<UIAssets>,
+    query: Query<(), With<SaveIndicator>>,
+) {
+    if !query.is_empty() {
+        return;
+    }
+
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text::with_section(
+                "Saving...",
+                TextStyle {
+                    font: ui_assets.font.clone(),
+                    font_size: 20.,
+                    color: Color::WHITE,
+                },
+                TextAlignment::default(),
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(15.),
+                    bottom: Val::Px(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(SaveIndicator);
+}
+
+/// Components of items that get saved.
+type SavedItem = (
+    &'static Transform,
+    &'static Velocity,
+    &'static Item,
+    Option<&'static PlayerAttractor>,
+    Option<&'static Frozen>,
+);
+
+/// Components of the player that get saved.
+type SavedPlayer = (
+    &'static Transform,
+    &'static Velocity,
+    &'static TextureAtlasSprite,
+    &'static Inventory,
+    &'static Health,
+);
+
+/// Everything in the ECS that gets saved, shared by the save systems. The
+/// world's resources are missing until a world has been loaded.
+#[derive(SystemParam)]
+struct SavedWorld<'w, 's> {
+    metadata: Option<Res<'w, WorldMetadata>>,
+    world_spawn: Option<Res<'w, WorldSpawn>>,
+    world_clock: Option<Res<'w, WorldClock>>,
+    world_store: Option<ResMut<'w, WorldStore>>,
+    item_query: Query<'w, 's, SavedItem>,
+    player_query: Query<'w, 's, SavedPlayer, With<Player>>,
+}
+
+impl SavedWorld<'_, '_> {
+    /// Copies everything that gets saved out of the ECS. Returns `None` if
+    /// no world has been loaded or the player hasn't been spawned yet.
+    fn snapshot(&mut self) -> Option<WorldSnapshot> {
+        let metadata = self.metadata.as_deref()?;
+        let world_spawn = self.world_spawn.as_deref()?;
+        let world_clock = self.world_clock.as_deref()?;
+        let world_store = self.world_store.as_deref_mut()?;
+
+        let (player_tf, player_velocity, player_sprite, player_inv, player_health) =
+            self.player_query.get_single().ok()?;
+        let player = PlayerSaveData {
+            position: player_tf.translation.truncate().into(),
+            velocity: player_velocity.linvel.into(),
+            facing_left: player_sprite.flip_x,
+            inventory_slots: player_inv.slots.clone(),
+            max_slots: player_inv.max_slots,
+            selected_slot: player_inv.selected_slot,
+            health: player_health.current,
+        };
+
+        let items = self
+            .item_query
+            .iter()
+            .map(|(item_tf, item_velocity, item, attractor, frozen)| {
+                // Frozen items carry on with the velocity they had before freezing
+                let velocity = frozen.map_or(item_velocity, |frozen| &frozen.0);
+
+                ItemData {
+                    item_name: item.item_name.clone(),
+                    position: item_tf.translation.truncate().into(),
+                    rotation: item_tf.rotation.to_euler(EulerRot::ZYX).0,
+                    velocity: velocity.linvel.into(),
+                    angular_velocity: velocity.angvel,
+                    picked_up: item.picked_up,
+                    attractor_strength: attractor.map(|attractor| attractor.strength),
+                }
+            })
+            .collect();
+
+        let world_data = WorldSaveData {
+            player_spawn: PositionData {
+                x: world_spawn.0.x as i32,
+                y: world_spawn.0.y as i32,
+            },
+            items,
+            player: Some(player),
+            world_time: world_clock.elapsed(),
+            legacy_blocks: None,
+        };
+
+        let metadata = WorldMetadata {
+            last_played: metadata::unix_time_now(),
+            game_version: env!("CARGO_PKG_VERSION").to_owned(),
+            ..metadata.clone()
+        };
+
+        Some(WorldSnapshot {
+            metadata,
+            world_data,
+            dirty_chunks: world_store.take_dirty_chunks(),
+        })
+    }
+}
+
+/// Serializes and writes a world snapshot on the `IoTaskPool`.
+fn spawn_save_task(
+    io_pool: &IoTaskPool,
+    world_name: String,
+    snapshot: WorldSnapshot,
+) -> Task<Result<(), SaveError>> {
+    io_pool.spawn(async move {
+        region::save_chunks(&world_name, snapshot.dirty_chunks)?;
+        write_world_data(&world_name, &snapshot.metadata, &snapshot.world_data)
+    })
+}
+
+/// Loads a world's metadata and data from its save file. A world that doesn't
+/// exist yet is generated and saved first. Backups aren't loaded in place of a
+/// corrupted save file, the player is asked whether to restore one instead. Blocks
+/// are left in the region files, to be read by the `WorldStore` as needed.
+fn load_world_data(world_name: &str) -> Result<(WorldMetadata, WorldSaveData), SaveError> {
+    slots::validate_world_name(world_name)?;
+
+    // Path to directory that holds save files
+    let save_data_path = std::path::Path::new(SAVE_DATA_PATH);
+
+    if !save_data_path.is_dir() {
+        // Create save data directory if doesn't exist
+        std::fs::create_dir_all(save_data_path)?;
+    }
+
+    slots::migrate_legacy_save()?;
+
+    if !slots::world_exists(world_name) {
+        slots::create_world(world_name, world_gen::seed_from_args())?;
+    }
+
+    let (metadata, mut world_data) =
+        format::decode_world(&std::fs::read(slots::world_save_path(world_name))?)?;
+    let metadata = metadata.unwrap_or_else(|| WorldMetadata::new(world_name));
+
+    // Move blocks of older saves into region files
+    if let Some(legacy_blocks) = world_data.legacy_blocks.take() {
+        write_chunks(
+            world_name,
+            region::group_into_chunks(legacy_blocks, Vec::new(), Vec::new()),
+        )?;
+        write_world_data(world_name, &metadata, &world_data)?;
+    }
+
+    Ok((metadata, world_data))
+}
+
+/// Writes all of the given chunks into the world's region files.
+fn write_chunks(world_name: &str, chunks: HashMap<IVec2, ChunkData>) -> Result<(), SaveError> {
+    let chunks = chunks
+        .into_iter()
+        .map(|(chunk_pos, chunk)| (chunk_pos, Some(chunk)))
+        .collect();
+    region::save_chunks(world_name, chunks)
+}
+
+/// Serializes world data and its metadata and writes them to the world's save file.
+fn write_world_data(
+    world_name: &str,
+    metadata: &WorldMetadata,
+    world_data: &WorldSaveData,
+) -> Result<(), SaveError> {
+    let world_data_serialized = format::encode_world(metadata, world_data)?;
+    storage::write_atomic(&slots::world_save_path(world_name), &world_data_serialized)?;
+    Ok(())
+}
@@ -0,0 +1,614 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use bevy::{math::IVec2, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    compression,
+    format::{read_header, write_header},
+    slots, storage, BlockData, LiquidData, PositionData, SaveError,
+};
+use crate::{coords, liquids::LiquidKind, tile_map::TileLayer};
+
+/// Width and height of a region file, in chunks.
+const REGION_SIZE: i32 = 16;
+
+/// Directory inside a world's directory that holds its region files.
+const REGIONS_DIR: &str = "regions";
+
+const REGION_MAGIC: &[u8; 4] = b"TCRG";
+
+/// Version 1 stored chunks as plain `ChunkData`, version 2 palette-encodes
+/// them and compresses the whole region, version 3 adds walls, version 4
+/// adds liquids, version 5 adds the materials of auto-tiled tiles and
+/// version 6 compresses every chunk on its own behind an offset table.
+const REGION_VERSION: u32 = 6;
+
+/// All the blocks, walls and liquids inside a single chunk.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ChunkData {
+    pub blocks: Vec<BlockData>,
+    pub walls: Vec<BlockData>,
+    pub liquids: Vec<LiquidData>,
+}
+
+impl ChunkData {
+    pub fn layer(&self, layer: TileLayer) -> &Vec<BlockData> {
+        match layer {
+            TileLayer::Block => &self.blocks,
+            TileLayer::Wall => &self.walls,
+        }
+    }
+
+    pub fn layer_mut(&mut self, layer: TileLayer) -> &mut Vec<BlockData> {
+        match layer {
+            TileLayer::Block => &mut self.blocks,
+            TileLayer::Wall => &mut self.walls,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty() && self.walls.is_empty() && self.liquids.is_empty()
+    }
+}
+
+/// How a chunk is stored on disk. Tile set names are only stored once per
+/// chunk in the palette, shared by blocks and walls, and tile positions are
+/// relative to the chunk.
+#[derive(Serialize, Deserialize)]
+struct PaletteChunk {
+    palette: Vec<String>,
+    blocks: Vec<PaletteBlock>,
+    walls: Vec<PaletteBlock>,
+    liquids: Vec<PaletteLiquid>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PaletteBlock {
+    local_x: u8,
+    local_y: u8,
+    palette_index: u16,
+    tile_index: u32,
+    material: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PaletteLiquid {
+    local_x: u8,
+    local_y: u8,
+    kind: LiquidKind,
+    level: u8,
+}
+
+/// Where a chunk is stored in a region file. A region file starts with the
+/// offset table of all its chunks, followed by the compressed chunks.
+#[derive(Serialize, Deserialize)]
+struct ChunkOffset {
+    chunk_x: i32,
+    chunk_y: i32,
+    /// Start of the compressed chunk, counted from the end of the offset table.
+    offset: u32,
+    length: u32,
+}
+
+/// Contents of a region file. Every chunk is encoded and compressed on its
+/// own, so that chunks that haven't changed can be written back without
+/// decoding or compressing them again.
+type RegionChunks = BTreeMap<(i32, i32), Vec<u8>>;
+
+/// Region file that a chunk is stored in.
+pub fn region_pos(chunk_pos: IVec2) -> IVec2 {
+    IVec2::new(
+        chunk_pos.x.div_euclid(REGION_SIZE),
+        chunk_pos.y.div_euclid(REGION_SIZE),
+    )
+}
+
+fn regions_dir(world_name: &str) -> PathBuf {
+    slots::world_dir(world_name).join(REGIONS_DIR)
+}
+
+fn region_path(world_name: &str, region_pos: IVec2) -> PathBuf {
+    regions_dir(world_name).join(format!("r.{}.{}.region", region_pos.x, region_pos.y))
+}
+
+/// Sorts blocks, walls and liquids into the chunks they belong to.
+pub fn group_into_chunks(
+    blocks: impl IntoIterator<Item = BlockData>,
+    walls: impl IntoIterator<Item = BlockData>,
+    liquids: impl IntoIterator<Item = LiquidData>,
+) -> HashMap<IVec2, ChunkData> {
+    let mut chunks = HashMap::<IVec2, ChunkData>::default();
+
+    let tiles = blocks
+        .into_iter()
+        .map(|block| (TileLayer::Block, block))
+        .chain(walls.into_iter().map(|wall| (TileLayer::Wall, wall)));
+    for (layer, tile) in tiles {
+        let tile_pos = IVec2::new(tile.tile_pos.x, tile.tile_pos.y);
+        chunks
+            .entry(coords::tile_to_chunk(tile_pos))
+            .or_default()
+            .layer_mut(layer)
+            .push(tile);
+    }
+
+    for liquid in liquids {
+        let tile_pos = IVec2::new(liquid.tile_pos.x, liquid.tile_pos.y);
+        chunks
+            .entry(coords::tile_to_chunk(tile_pos))
+            .or_default()
+            .liquids
+            .push(liquid);
+    }
+
+    // Keep the tile order stable so that unchanged chunks are written the same
+    for chunk in chunks.values_mut() {
+        for layer in TileLayer::ALL {
+            chunk
+                .layer_mut(layer)
+                .sort_by_key(|tile| (tile.tile_pos.y, tile.tile_pos.x));
+        }
+        chunk
+            .liquids
+            .sort_by_key(|liquid| (liquid.tile_pos.y, liquid.tile_pos.x));
+    }
+
+    chunks
+}
+
+fn encode_chunk(chunk: &ChunkData) -> Result<Vec<u8>, SaveError> {
+    let mut palette = Vec::<String>::new();
+    let blocks = encode_tiles(&chunk.blocks, &mut palette);
+    let walls = encode_tiles(&chunk.walls, &mut palette);
+
+    let liquids = chunk
+        .liquids
+        .iter()
+        .map(|liquid| {
+            let local_pos = coords::tile_to_local(IVec2::new(liquid.tile_pos.x, liquid.tile_pos.y));
+
+            PaletteLiquid {
+                local_x: local_pos.x as u8,
+                local_y: local_pos.y as u8,
+                kind: liquid.kind,
+                level: liquid.level,
+            }
+        })
+        .collect();
+
+    compress_chunk(&PaletteChunk {
+        palette,
+        blocks,
+        walls,
+        liquids,
+    })
+}
+
+fn compress_chunk(chunk: &PaletteChunk) -> Result<Vec<u8>, SaveError> {
+    compression::compress(&bincode::serialize(chunk)?)
+}
+
+/// Turns tiles into palette tiles, adding their tile sets to the palette.
+fn encode_tiles(tiles: &[BlockData], palette: &mut Vec<String>) -> Vec<PaletteBlock> {
+    let mut palette_tiles = Vec::with_capacity(tiles.len());
+
+    for tile in tiles {
+        let local_pos = coords::tile_to_local(IVec2::new(tile.tile_pos.x, tile.tile_pos.y));
+        let palette_index = match palette.iter().position(|name| *name == tile.tile_set) {
+            Some(palette_index) => palette_index,
+            None => {
+                palette.push(tile.tile_set.clone());
+                palette.len() - 1
+            }
+        };
+
+        palette_tiles.push(PaletteBlock {
+            local_x: local_pos.x as u8,
+            local_y: local_pos.y as u8,
+            palette_index: palette_index as u16,
+            tile_index: tile.tile_index as u32,
+            material: tile.material.map(|material| material as u32),
+        });
+    }
+
+    palette_tiles
+}
+
+fn decode_chunk(chunk_pos: IVec2, bytes: &[u8]) -> Result<ChunkData, SaveError> {
+    let palette_chunk: PaletteChunk = bincode::deserialize(&compression::decompress(bytes)?)?;
+
+    let liquids = palette_chunk
+        .liquids
+        .into_iter()
+        .map(|liquid| {
+            let tile_pos = coords::chunk_to_tile(chunk_pos)
+                + IVec2::new(liquid.local_x as i32, liquid.local_y as i32);
+
+            LiquidData {
+                kind: liquid.kind,
+                level: liquid.level,
+                tile_pos: PositionData {
+                    x: tile_pos.x,
+                    y: tile_pos.y,
+                },
+            }
+        })
+        .collect();
+
+    Ok(ChunkData {
+        blocks: decode_tiles(chunk_pos, palette_chunk.blocks, &palette_chunk.palette)?,
+        walls: decode_tiles(chunk_pos, palette_chunk.walls, &palette_chunk.palette)?,
+        liquids,
+    })
+}
+
+fn decode_tiles(
+    chunk_pos: IVec2,
+    palette_tiles: Vec<PaletteBlock>,
+    palette: &[String],
+) -> Result<Vec<BlockData>, SaveError> {
+    let mut tiles = Vec::with_capacity(palette_tiles.len());
+
+    for tile in palette_tiles {
+        let tile_set = palette.get(tile.palette_index as usize).ok_or_else(|| {
+            SaveError::Corrupted(Box::new(bincode::ErrorKind::Custom(format!(
+                "Tile in chunk {} refers to missing palette entry {}",
+                chunk_pos, tile.palette_index
+            ))))
+        })?;
+
+        let tile_pos =
+            coords::chunk_to_tile(chunk_pos) + IVec2::new(tile.local_x as i32, tile.local_y as i32);
+
+        tiles.push(BlockData {
+            tile_set: tile_set.clone(),
+            tile_index: tile.tile_index as usize,
+            material: tile.material.map(|material| material as usize),
+            tile_pos: PositionData {
+                x: tile_pos.x,
+                y: tile_pos.y,
+            },
+        });
+    }
+
+    Ok(tiles)
+}
+
+/// Loads every chunk stored in a single region file.
+pub fn load_region_chunks(
+    world_name: &str,
+    region_pos: IVec2,
+) -> Result<HashMap<IVec2, ChunkData>, SaveError> {
+    read_region(&region_path(world_name, region_pos))?
+        .into_iter()
+        .map(|((x, y), chunk_bytes)| {
+            let chunk_pos = IVec2::new(x, y);
+            Ok((chunk_pos, decode_chunk(chunk_pos, &chunk_bytes)?))
+        })
+        .collect()
+}
+
+/// Loads every chunk of a world.
+pub fn load_all_chunks(world_name: &str) -> Result<HashMap<IVec2, ChunkData>, SaveError> {
+    let mut chunks = HashMap::default();
+    let regions_dir = regions_dir(world_name);
+
+    if !regions_dir.is_dir() {
+        return Ok(chunks);
+    }
+
+    for entry in std::fs::read_dir(regions_dir)? {
+        let path = entry?.path();
+
+        if path
+            .extension()
+            .is_none_or(|extension| extension != "region")
+        {
+            continue;
+        }
+
+        for ((x, y), chunk_bytes) in read_region(&path)? {
+            let chunk_pos = IVec2::new(x, y);
+            chunks.insert(chunk_pos, decode_chunk(chunk_pos, &chunk_bytes)?);
+        }
+    }
+
+    Ok(chunks)
+}
+
+/// Writes the given chunks into their region files, removing the
+/// chunks that map to `None`. Region files without any of the
+/// given chunks are left untouched. Corrupted region files are
+/// quarantined and replaced by ones with only the given chunks, the
+/// same way the `WorldStore` treats them as empty when loading them.
+pub fn save_chunks(
+    world_name: &str,
+    chunks: HashMap<IVec2, Option<ChunkData>>,
+) -> Result<(), SaveError> {
+    let mut regions = HashMap::<IVec2, Vec<(IVec2, Option<ChunkData>)>>::default();
+    for (chunk_pos, chunk) in chunks {
+        regions
+            .entry(region_pos(chunk_pos))
+            .or_default()
+            .push((chunk_pos, chunk));
+    }
+
+    if !regions.is_empty() {
+        std::fs::create_dir_all(regions_dir(world_name))?;
+    }
+
+    for (region_pos, region_changes) in regions {
+        let path = region_path(world_name, region_pos);
+        let mut region = match read_region(&path) {
+            Ok(region) => region,
+            Err(e) if e.is_corruption() => {
+                let corrupt_path = storage::quarantine(&path)?;
+                eprintln!(
+                    "Region {} of world {} is corrupted, moved it to {} and writing a new one: {}",
+                    region_pos,
+                    world_name,
+                    corrupt_path.display(),
+                    e
+                );
+                RegionChunks::new()
+            }
+            Err(e) => return Err(e),
+        };
+
+        for (chunk_pos, chunk) in region_changes {
+            let key = (chunk_pos.x, chunk_pos.y);
+
+            match chunk {
+                Some(chunk) => {
+                    region.insert(key, encode_chunk(&chunk)?);
+                }
+                None => {
+                    region.remove(&key);
+                }
+            }
+        }
+
+        storage::write_atomic(&path, &encode_region(&region)?)?;
+    }
+
+    Ok(())
+}
+
+/// Reads a region file, treating a missing file as an empty region.
+fn read_region(path: &std::path::Path) -> Result<RegionChunks, SaveError> {
+    if !path.is_file() {
+        return Ok(RegionChunks::new());
+    }
+
+    storage::read_with_fallback(path, decode_region)
+}
+
+/// Writes the offset table and compressed chunks of a region file.
+fn encode_region(region: &RegionChunks) -> Result<Vec<u8>, SaveError> {
+    let mut offset = 0;
+    let offsets: Vec<ChunkOffset> = region
+        .iter()
+        .map(|(&(chunk_x, chunk_y), chunk_bytes)| {
+            let chunk_offset = ChunkOffset {
+                chunk_x,
+                chunk_y,
+                offset,
+                length: chunk_bytes.len() as u32,
+            };
+            offset += chunk_offset.length;
+            chunk_offset
+        })
+        .collect();
+
+    let mut region_bytes = write_header(REGION_MAGIC, REGION_VERSION);
+    region_bytes.extend(bincode::serialize(&offsets)?);
+    for chunk_bytes in region.values() {
+        region_bytes.extend_from_slice(chunk_bytes);
+    }
+
+    Ok(region_bytes)
+}
+
+/// Splits the payload of a region file into its compressed chunks, using its offset table.
+fn split_chunks(payload: &[u8]) -> Result<RegionChunks, SaveError> {
+    let offsets: Vec<ChunkOffset> = bincode::deserialize(payload)?;
+    let chunks_bytes = &payload[bincode::serialized_size(&offsets)? as usize..];
+
+    offsets
+        .into_iter()
+        .map(|chunk_offset| {
+            let start = chunk_offset.offset as usize;
+            let chunk_bytes = chunks_bytes
+                .get(start..start + chunk_offset.length as usize)
+                .ok_or_else(|| {
+                    SaveError::Corrupted(Box::new(bincode::ErrorKind::Custom(format!(
+                        "Chunk ({}, {}) is cut off",
+                        chunk_offset.chunk_x, chunk_offset.chunk_y
+                    ))))
+                })?;
+
+            Ok((
+                (chunk_offset.chunk_x, chunk_offset.chunk_y),
+                chunk_bytes.to_vec(),
+            ))
+        })
+        .collect()
+}
+
+/// Decodes a region file of any version, re-encoding the chunks
+/// of older versions so that they match the current version.
+fn decode_region(bytes: &[u8]) -> Result<RegionChunks, SaveError> {
+    match read_header(bytes, REGION_MAGIC) {
+        Some((1, payload)) => {
+            let region: RegionChunks = bincode::deserialize(payload)?;
+
+            region
+                .into_iter()
+                .map(|((x, y), chunk_bytes)| {
+                    let chunk: v1::ChunkData = bincode::deserialize(&chunk_bytes)?;
+                    let blocks = chunk
+                        .blocks
+                        .into_iter()
+                        .map(|block| BlockData {
+                            tile_set: block.tile_set,
+                            tile_index: block.tile_index,
+                            material: None,
+                            tile_pos: block.tile_pos,
+                        })
+                        .collect();
+                    let chunk = ChunkData {
+                        blocks,
+                        ..Default::default()
+                    };
+                    Ok(((x, y), encode_chunk(&chunk)?))
+                })
+                .collect()
+        }
+        Some((version @ 2..=4, payload)) => {
+            let region: RegionChunks = bincode::deserialize(&compression::decompress(payload)?)?;
+
+            region
+                .into_iter()
+                .map(|((x, y), chunk_bytes)| {
+                    let chunk = upgrade_palette_chunk(version, &chunk_bytes)?;
+                    Ok(((x, y), compress_chunk(&chunk)?))
+                })
+                .collect()
+        }
+        // The chunks of version 5 are already current, they only need compressing
+        Some((5, payload)) => {
+            let region: RegionChunks = bincode::deserialize(&compression::decompress(payload)?)?;
+
+            region
+                .into_iter()
+                .map(|(chunk_pos, chunk_bytes)| {
+                    Ok((chunk_pos, compression::compress(&chunk_bytes)?))
+                })
+                .collect()
+        }
+        Some((REGION_VERSION, payload)) => split_chunks(payload),
+        Some((version, _)) => Err(SaveError::UnsupportedVersion(version)),
+        None => Err(SaveError::Corrupted(Box::new(bincode::ErrorKind::Custom(
+            "Missing region file header".to_owned(),
+        )))),
+    }
+}
+
+/// Decodes a palette-encoded chunk of region version 2, 3 or 4.
+fn upgrade_palette_chunk(version: u32, bytes: &[u8]) -> Result<PaletteChunk, SaveError> {
+    let chunk = match version {
+        2 => {
+            let chunk: v2::PaletteChunk = bincode::deserialize(bytes)?;
+            PaletteChunk {
+                palette: chunk.palette,
+                blocks: upgrade_palette_blocks(chunk.blocks),
+                walls: Vec::new(),
+                liquids: Vec::new(),
+            }
+        }
+        3 => {
+            let chunk: v3::PaletteChunk = bincode::deserialize(bytes)?;
+            PaletteChunk {
+                palette: chunk.palette,
+                blocks: upgrade_palette_blocks(chunk.blocks),
+                walls: upgrade_palette_blocks(chunk.walls),
+                liquids: Vec::new(),
+            }
+        }
+        _ => {
+            let chunk: v4::PaletteChunk = bincode::deserialize(bytes)?;
+            PaletteChunk {
+                palette: chunk.palette,
+                blocks: upgrade_palette_blocks(chunk.blocks),
+                walls: upgrade_palette_blocks(chunk.walls),
+                liquids: chunk.liquids,
+            }
+        }
+    };
+
+    Ok(chunk)
+}
+
+/// Tiles from before materials were saved are made of the tile they are drawn with.
+fn upgrade_palette_blocks(blocks: Vec<v4::PaletteBlock>) -> Vec<PaletteBlock> {
+    blocks
+        .into_iter()
+        .map(|block| PaletteBlock {
+            local_x: block.local_x,
+            local_y: block.local_y,
+            palette_index: block.palette_index,
+            tile_index: block.tile_index,
+            material: None,
+        })
+        .collect()
+}
+
+/// Version 1: chunks are stored as they are in memory, without walls.
+mod v1 {
+    use serde::Deserialize;
+
+    use crate::save_data::PositionData;
+
+    #[derive(Deserialize)]
+    pub struct ChunkData {
+        pub blocks: Vec<BlockData>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct BlockData {
+        pub tile_set: String,
+        pub tile_index: usize,
+        pub tile_pos: PositionData,
+    }
+}
+
+/// Version 2: palette-encoded chunks, without walls.
+mod v2 {
+    use serde::Deserialize;
+
+    use super::v4::PaletteBlock;
+
+    #[derive(Deserialize)]
+    pub(super) struct PaletteChunk {
+        pub(super) palette: Vec<String>,
+        pub(super) blocks: Vec<PaletteBlock>,
+    }
+}
+
+/// Version 3: palette-encoded chunks, without liquids.
+mod v3 {
+    use serde::Deserialize;
+
+    use super::v4::PaletteBlock;
+
+    #[derive(Deserialize)]
+    pub(super) struct PaletteChunk {
+        pub(super) palette: Vec<String>,
+        pub(super) blocks: Vec<PaletteBlock>,
+        pub(super) walls: Vec<PaletteBlock>,
+    }
+}
+
+/// Version 4: palette-encoded chunks, without the materials of tiles.
+mod v4 {
+    use serde::Deserialize;
+
+    use super::PaletteLiquid;
+
+    #[derive(Deserialize)]
+    pub(super) struct PaletteChunk {
+        pub(super) palette: Vec<String>,
+        pub(super) blocks: Vec<PaletteBlock>,
+        pub(super) walls: Vec<PaletteBlock>,
+        pub(super) liquids: Vec<PaletteLiquid>,
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct PaletteBlock {
+        pub(super) local_x: u8,
+        pub(super) local_y: u8,
+        pub(super) palette_index: u16,
+        pub(super) tile_index: u32,
+    }
+}
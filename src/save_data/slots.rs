@@ -0,0 +1,261 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    format, generate_world, metadata, storage, write_chunks, write_world_data, SaveError,
+    WorldMetadata, SAVE_DATA_PATH,
+};
+
+/// Name of the file inside a world's directory that holds its save data.
+pub const WORLD_SAVE_FILE: &str = "world.save";
+
+/// Name of the world used when no other world has been selected.
+const DEFAULT_WORLD_NAME: &str = "world0";
+
+/// Resource naming the world that gets loaded when entering `GameState::Game`
+/// and that the save systems write to.
+pub struct ActiveWorld(pub String);
+
+impl ActiveWorld {
+    /// Picks the world named by the `--world <name>` command line argument,
+    /// falling back to the default world if it's missing or invalid.
+    pub fn from_args() -> Self {
+        let world_name = std::env::args()
+            .skip_while(|arg| arg != "--world")
+            .nth(1)
+            .unwrap_or_else(|| DEFAULT_WORLD_NAME.to_owned());
+
+        if let Err(e) = validate_world_name(&world_name) {
+            eprintln!("{}, using world {} instead", e, DEFAULT_WORLD_NAME);
+            return Self(DEFAULT_WORLD_NAME.to_owned());
+        }
+
+        Self(world_name)
+    }
+}
+
+/// Directory that holds all of a world's save files.
+pub fn world_dir(world_name: &str) -> PathBuf {
+    Path::new(SAVE_DATA_PATH).join(world_name)
+}
+
+/// Path to a world's main save file.
+pub fn world_save_path(world_name: &str) -> PathBuf {
+    world_dir(world_name).join(WORLD_SAVE_FILE)
+}
+
+pub fn world_exists(world_name: &str) -> bool {
+    world_save_path(world_name).is_file()
+}
+
+/// Returns the names of all worlds that have a save file, sorted alphabetically.
+pub fn list_worlds() -> Result<Vec<String>, SaveError> {
+    let save_data_path = Path::new(SAVE_DATA_PATH);
+
+    if !save_data_path.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut worlds = std::fs::read_dir(save_data_path)?
+        .filter_map(|entry| match entry {
+            Ok(entry) => {
+                let world_name = entry.file_name().to_str()?.to_owned();
+
+                if entry.path().join(WORLD_SAVE_FILE).is_file() {
+                    Some(world_name)
+                } else {
+                    None
+                }
+            }
+            Err(e) => {
+                eprintln!("Error while listing world saves: {}", e);
+                None
+            }
+        })
+        .collect::<Vec<String>>();
+
+    worlds.sort();
+    Ok(worlds)
+}
+
+/// Reads a world's metadata without deserializing the rest of the world.
+pub fn world_metadata(world_name: &str) -> Result<WorldMetadata, SaveError> {
+    let metadata = format::read_metadata(&world_save_path(world_name))?;
+    Ok(metadata.unwrap_or_else(|| WorldMetadata::new(world_name)))
+}
+
+/// Creates a new world, generating its terrain from the given seed.
+pub fn create_world(world_name: &str, seed: u64) -> Result<(), SaveError> {
+    validate_world_name(world_name)?;
+    ensure_world_absent(world_name)?;
+    write_new_world(world_name, seed)
+}
+
+/// Returns the newest backup of a world's save file that can still be loaded.
+pub fn restorable_backup(world_name: &str) -> Option<PathBuf> {
+    storage::newest_valid_backup(&world_save_path(world_name), format::decode_world)
+}
+
+/// Replaces a world's save file with its newest backup that can still be
+/// loaded. The replaced save file is kept as `world.save.corrupt`.
+pub fn restore_backup(world_name: &str) -> Result<(), SaveError> {
+    ensure_world_present(world_name)?;
+
+    let backup_path = restorable_backup(world_name)
+        .ok_or_else(|| SaveError::NoValidBackup(world_name.to_owned()))?;
+    let save_path = world_save_path(world_name);
+
+    if save_path.is_file() {
+        storage::quarantine(&save_path)?;
+    }
+
+    std::fs::copy(backup_path, save_path)?;
+    Ok(())
+}
+
+/// Generates a world again in place of one that can't be loaded. Nothing
+/// is deleted: the old save file, its backups and its regions are moved
+/// into a `corrupt-<unix time>` directory inside the world's directory,
+/// where saving the new world won't rotate them away.
+pub fn start_fresh(world_name: &str, seed: u64) -> Result<(), SaveError> {
+    ensure_world_present(world_name)?;
+
+    let world_dir = world_dir(world_name);
+    let corrupt_dir = world_dir.join(format!("corrupt-{}", metadata::unix_time_now()));
+    std::fs::create_dir(&corrupt_dir)?;
+
+    for entry in std::fs::read_dir(&world_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+
+        // Leave the files of earlier fresh starts where they are
+        if file_name.to_string_lossy().starts_with("corrupt-") {
+            continue;
+        }
+
+        std::fs::rename(entry.path(), corrupt_dir.join(file_name))?;
+    }
+
+    write_new_world(world_name, seed)
+}
+
+pub fn rename_world(world_name: &str, new_name: &str) -> Result<(), SaveError> {
+    validate_world_name(world_name)?;
+    validate_world_name(new_name)?;
+    ensure_world_present(world_name)?;
+    ensure_world_absent(new_name)?;
+
+    std::fs::rename(world_dir(world_name), world_dir(new_name))?;
+    set_metadata_name(new_name)
+}
+
+/// Copies every save file of a world into a new world directory.
+pub fn duplicate_world(world_name: &str, new_name: &str) -> Result<(), SaveError> {
+    validate_world_name(world_name)?;
+    validate_world_name(new_name)?;
+    ensure_world_present(world_name)?;
+    ensure_world_absent(new_name)?;
+
+    copy_dir_all(&world_dir(world_name), &world_dir(new_name))?;
+    set_metadata_name(new_name)
+}
+
+pub fn delete_world(world_name: &str) -> Result<(), SaveError> {
+    validate_world_name(world_name)?;
+    ensure_world_present(world_name)?;
+    std::fs::remove_dir_all(world_dir(world_name))?;
+    Ok(())
+}
+
+/// Moves the single save file used by older versions of the
+/// game (`world_saves/world0.save`) into its own world directory.
+pub fn migrate_legacy_save() -> Result<(), SaveError> {
+    let legacy_path = Path::new(SAVE_DATA_PATH).join("world0.save");
+
+    if legacy_path.is_file() && !world_exists(DEFAULT_WORLD_NAME) {
+        std::fs::create_dir_all(world_dir(DEFAULT_WORLD_NAME))?;
+        std::fs::rename(legacy_path, world_save_path(DEFAULT_WORLD_NAME))?;
+    }
+
+    Ok(())
+}
+
+/// World names are used as directory names, so they must not be able
+/// to point outside of `SAVE_DATA_PATH`.
+pub fn validate_world_name(world_name: &str) -> Result<(), SaveError> {
+    let invalid = world_name.trim().is_empty()
+        || world_name == "."
+        || world_name == ".."
+        || world_name.contains(['/', '\\', ':']);
+
+    if invalid {
+        Err(SaveError::InvalidWorldName(world_name.to_owned()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Updates the name stored in a world's metadata to match its directory.
+fn set_metadata_name(world_name: &str) -> Result<(), SaveError> {
+    let (metadata, world_data) =
+        storage::read_with_fallback(&world_save_path(world_name), format::decode_world)?;
+    let metadata = WorldMetadata {
+        name: world_name.to_owned(),
+        ..metadata.unwrap_or_else(|| WorldMetadata::new(world_name))
+    };
+
+    write_world_data(world_name, &metadata, &world_data)
+}
+
+/// Checks that a world's directory exists. Invalid names are rejected
+/// first, as they might name a directory outside of `SAVE_DATA_PATH`.
+pub fn ensure_world_present(world_name: &str) -> Result<(), SaveError> {
+    validate_world_name(world_name)?;
+
+    if world_dir(world_name).is_dir() {
+        Ok(())
+    } else {
+        Err(SaveError::WorldNotFound(world_name.to_owned()))
+    }
+}
+
+fn ensure_world_absent(world_name: &str) -> Result<(), SaveError> {
+    if world_dir(world_name).exists() {
+        Err(SaveError::WorldAlreadyExists(world_name.to_owned()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Generates a world's terrain and writes its save files, without
+/// checking whether the world already has any.
+fn write_new_world(world_name: &str, seed: u64) -> Result<(), SaveError> {
+    let (world_data, chunks) = generate_world(seed)?;
+    let metadata = WorldMetadata {
+        seed,
+        ..WorldMetadata::new(world_name)
+    };
+
+    std::fs::create_dir_all(world_dir(world_name))?;
+    write_chunks(world_name, chunks)?;
+    write_world_data(world_name, &metadata, &world_data)
+}
+
+fn copy_dir_all(from: &Path, to: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(to)?;
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use std::{
+    ffi::OsString,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use super::SaveError;
+
+/// Number of previous versions kept next to a save file,
+/// `<file>.1` being the newest and `<file>.3` the oldest.
+const SAVE_BACKUP_COUNT: usize = 3;
+
+/// Replaces the file at `path` without ever leaving a half-written file behind.
+/// The data is written to a temporary file first and then renamed over the
+/// original, after the original has been rotated into the backups.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp_path = suffixed_path(path, "tmp");
+
+    {
+        let mut tmp_file = std::fs::File::create(&tmp_path)?;
+        tmp_file.write_all(bytes)?;
+        tmp_file.sync_all()?;
+    }
+
+    rotate_backups(path)?;
+    std::fs::rename(&tmp_path, path)
+}
+
+/// Reads and decodes the file at `path`. If it is missing or can't be
+/// decoded, the backups are tried from newest to oldest. The error of
+/// the primary file is returned if none of them can be loaded.
+pub fn read_with_fallback<T>(
+    path: &Path,
+    decode: impl Fn(&[u8]) -> Result<T, SaveError>,
+) -> Result<T, SaveError> {
+    let read_and_decode = |path: &Path| decode(&std::fs::read(path)?);
+
+    let primary_error = match read_and_decode(path) {
+        Ok(data) => return Ok(data),
+        Err(e) => e,
+    };
+
+    eprintln!("Error loading {}: {}", path.display(), primary_error);
+
+    for backup_number in 1..=SAVE_BACKUP_COUNT {
+        let backup_path = backup_path(path, backup_number);
+
+        if !backup_path.is_file() {
+            continue;
+        }
+
+        match read_and_decode(&backup_path) {
+            Ok(data) => {
+                eprintln!("Loaded backup {} instead", backup_path.display());
+                return Ok(data);
+            }
+            Err(e) => eprintln!("Error loading {}: {}", backup_path.display(), e),
+        }
+    }
+
+    Err(primary_error)
+}
+
+/// Returns the newest backup of the file at `path` that can be decoded, if any.
+pub fn newest_valid_backup<T>(
+    path: &Path,
+    decode: impl Fn(&[u8]) -> Result<T, SaveError>,
+) -> Option<PathBuf> {
+    (1..=SAVE_BACKUP_COUNT)
+        .map(|backup_number| backup_path(path, backup_number))
+        .filter(|backup_path| backup_path.is_file())
+        .find(|backup_path| match std::fs::read(backup_path) {
+            Ok(bytes) => decode(&bytes).is_ok(),
+            Err(_) => false,
+        })
+}
+
+/// Moves a file that can't be decoded out of the way, so that a new one can
+/// be written in its place without losing what's left of the old one.
+/// Returns the path it was moved to, `<file>.corrupt`.
+pub fn quarantine(path: &Path) -> io::Result<PathBuf> {
+    let corrupt_path = suffixed_path(path, "corrupt");
+    std::fs::rename(path, &corrupt_path)?;
+    Ok(corrupt_path)
+}
+
+pub fn backup_path(path: &Path, backup_number: usize) -> PathBuf {
+    suffixed_path(path, &backup_number.to_string())
+}
+
+/// Shifts every backup of `path` one place back, dropping the oldest,
+/// and copies the current file into the newest backup slot. The current
+/// file is copied rather than moved so that it stays in place until the
+/// new data is renamed over it.
+fn rotate_backups(path: &Path) -> io::Result<()> {
+    if !path.is_file() {
+        return Ok(());
+    }
+
+    let oldest_backup = backup_path(path, SAVE_BACKUP_COUNT);
+    if oldest_backup.is_file() {
+        std::fs::remove_file(oldest_backup)?;
+    }
+
+    for backup_number in (1..SAVE_BACKUP_COUNT).rev() {
+        let backup = backup_path(path, backup_number);
+
+        if backup.is_file() {
+            std::fs::rename(backup, backup_path(path, backup_number + 1))?;
+        }
+    }
+
+    std::fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+/// Appends `.<suffix>` to the file name of `path`, e.g. `world.save` -> `world.save.1`.
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(".");
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
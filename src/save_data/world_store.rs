@@ -0,0 +1,154 @@
+use bevy::{
+    math::IVec2,
+    utils::{HashMap, HashSet},
+};
+
+use super::{region, BlockData, ChunkData, LiquidData, PositionData};
+use crate::{
+    coords,
+    liquids::LiquidKind,
+    tile_map::{Tile, TileLayer},
+};
+
+/// Resource holding the blocks, walls and liquids of every chunk that has been read from the
+/// active world's region files, whether or not the chunk is spawned. Chunks
+/// are read a region at a time, the first time one of them is needed.
+pub struct WorldStore {
+    world_name: String,
+    chunks: HashMap<IVec2, ChunkData>,
+    loaded_regions: HashSet<IVec2>,
+    /// Chunks that changed since they were last saved.
+    dirty_chunks: HashSet<IVec2>,
+}
+
+impl WorldStore {
+    pub fn new(world_name: &str) -> Self {
+        Self {
+            world_name: world_name.to_owned(),
+            chunks: HashMap::default(),
+            loaded_regions: HashSet::default(),
+            dirty_chunks: HashSet::default(),
+        }
+    }
+
+    /// Blocks, walls and liquids of a chunk, or `None` if the chunk doesn't have any.
+    pub fn chunk(&mut self, chunk_pos: IVec2) -> Option<&ChunkData> {
+        self.load_region(chunk_pos);
+        self.chunks.get(&chunk_pos)
+    }
+
+    /// Puts a tile into a layer of its chunk, or removes the tile at
+    /// `tile_pos` in that layer if `tile` is `None`. The chunk is only marked
+    /// as changed if the tile is different from the one that was there before.
+    pub fn set_tile(&mut self, layer: TileLayer, tile_pos: IVec2, tile: Option<&Tile>) {
+        let chunk_pos = coords::tile_to_chunk(tile_pos);
+        self.load_region(chunk_pos);
+
+        let tiles = self.chunks.entry(chunk_pos).or_default().layer_mut(layer);
+        let existing = tiles
+            .iter()
+            .position(|tile| tile.tile_pos.x == tile_pos.x && tile.tile_pos.y == tile_pos.y);
+        let tile = tile.map(|tile| BlockData {
+            tile_set: tile.tile_set.clone(),
+            tile_index: tile.tile_index,
+            material: Some(tile.material).filter(|material| *material != tile.tile_index),
+            tile_pos: PositionData {
+                x: tile_pos.x,
+                y: tile_pos.y,
+            },
+        });
+
+        match (existing, tile) {
+            (Some(index), Some(tile)) => {
+                if tiles[index] == tile {
+                    return;
+                }
+
+                tiles[index] = tile;
+            }
+            (None, Some(tile)) => tiles.push(tile),
+            (Some(index), None) => {
+                tiles.swap_remove(index);
+            }
+            (None, None) => return,
+        }
+
+        self.dirty_chunks.insert(chunk_pos);
+    }
+
+    /// Fills a tile of its chunk with liquid, or removes the liquid at
+    /// `tile_pos` if `liquid` is `None`.
+    pub fn set_liquid(&mut self, tile_pos: IVec2, liquid: Option<(LiquidKind, u8)>) {
+        let chunk_pos = coords::tile_to_chunk(tile_pos);
+        self.load_region(chunk_pos);
+
+        let liquids = &mut self.chunks.entry(chunk_pos).or_default().liquids;
+        let existing = liquids
+            .iter()
+            .position(|liquid| liquid.tile_pos.x == tile_pos.x && liquid.tile_pos.y == tile_pos.y);
+
+        match (existing, liquid) {
+            (Some(index), Some((kind, level))) => {
+                let existing = &mut liquids[index];
+                if existing.kind == kind && existing.level == level {
+                    return;
+                }
+
+                existing.kind = kind;
+                existing.level = level;
+            }
+            (None, Some((kind, level))) => liquids.push(LiquidData {
+                kind,
+                level,
+                tile_pos: PositionData {
+                    x: tile_pos.x,
+                    y: tile_pos.y,
+                },
+            }),
+            (Some(index), None) => {
+                liquids.swap_remove(index);
+            }
+            (None, None) => return,
+        }
+
+        self.dirty_chunks.insert(chunk_pos);
+    }
+
+    /// Copies every chunk that changed since the last call, `None` for
+    /// chunks that no longer have any blocks, walls or liquids.
+    pub fn take_dirty_chunks(&mut self) -> HashMap<IVec2, Option<ChunkData>> {
+        let chunks = &self.chunks;
+
+        self.dirty_chunks
+            .drain()
+            .map(|chunk_pos| {
+                let chunk = chunks
+                    .get(&chunk_pos)
+                    .filter(|chunk| !chunk.is_empty())
+                    .cloned();
+                (chunk_pos, chunk)
+            })
+            .collect()
+    }
+
+    /// Marks every chunk as changed, so that the next save writes all of them.
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty_chunks.extend(self.chunks.keys().copied());
+    }
+
+    fn load_region(&mut self, chunk_pos: IVec2) {
+        let region_pos = region::region_pos(chunk_pos);
+
+        if !self.loaded_regions.insert(region_pos) {
+            return;
+        }
+
+        match region::load_region_chunks(&self.world_name, region_pos) {
+            Ok(chunks) => self.chunks.extend(chunks),
+            Err(e) => eprintln!(
+                "Error loading region {} of world {}, its chunks will be empty: {}",
+                region_pos, self.world_name, e
+            ),
+        }
+    }
+}
@@ -1,17 +1,258 @@
+use std::collections::BTreeMap;
+
 use bevy::{prelude::*, utils::HashMap};
-use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
 
-use crate::{components::Block, GameState, SPRITE_SCALE};
+use crate::{
+    auto_tile::AutoTileRules,
+    lighting::{self, MAX_LIGHT},
+    GameState,
+};
 
-const JUNGLE_FLOOR_SHEET: &str = "tile_sets/overworld/jungle_floor.png";
+const TILE_SETS_DIR: &str = "assets/tile_sets";
 pub const BLOCK_SIZE: f32 = 16.;
 
-type TileSets = HashMap<String, Handle<TextureAtlas>>;
+/// Colour that walls are multiplied with, so that they stand out from
+/// the blocks in front of them.
+const WALL_TINT: Color = Color::rgb(0.45, 0.45, 0.5);
+
+/// Loaded assets of every tile set, by name.
+pub type TileSets = HashMap<String, TileSetAssets>;
+
+pub struct TileSetAssets {
+    pub atlas: Handle<TextureAtlas>,
+    /// Materials that draw the tile set's sheet for chunk meshes,
+    /// one per light level from darkest to brightest.
+    pub materials: Vec<Handle<ColorMaterial>>,
+    /// Darker versions of `materials`, for walls.
+    pub wall_materials: Vec<Handle<ColorMaterial>>,
+}
+
+impl TileSetAssets {
+    /// Material for the tiles of a layer with the given light level.
+    pub fn material(&self, layer: TileLayer, light_level: u8) -> &Handle<ColorMaterial> {
+        let materials = match layer {
+            TileLayer::Block => &self.materials,
+            TileLayer::Wall => &self.wall_materials,
+        };
+        &materials[light_level.min(MAX_LIGHT) as usize]
+    }
+}
+
+/// A sprite sheet of tiles laid out in a grid, as described by the
+/// `assets/tile_sets/<name>/<name>.json` manifest of the tile set.
+#[derive(Deserialize)]
+pub struct TileSetDefinition {
+    /// Path of the sheet, relative to the tile set's directory.
+    pub sheet: String,
+    /// Width and height of a tile in the sheet, in pixels.
+    pub tile_size: f32,
+    pub columns: usize,
+    pub rows: usize,
+    /// Properties of every tile not listed in `tile_properties`.
+    pub properties: TileProperties,
+    /// Tiles that have their own properties, by tile index.
+    #[serde(default)]
+    pub tile_properties: BTreeMap<usize, TileProperties>,
+    /// Rules for picking tiles from neighbouring blocks, if the tile set has any.
+    #[serde(default)]
+    pub auto_tile: Option<AutoTileRules>,
+}
+
+#[derive(Deserialize)]
+pub struct TileProperties {
+    /// Whether the player and items collide with the tile.
+    pub solid: bool,
+    /// Seconds it takes to mine the tile with a mining speed of 1.
+    pub hardness: f32,
+    /// Item dropped by the tile when it is mined, if any.
+    pub drop: Option<String>,
+    /// Item dropped by the tile when it is mined as a wall, if any.
+    #[serde(default)]
+    pub wall_drop: Option<String>,
+    /// Brightness of the light given off by the tile, from 0 to 1.
+    #[serde(default)]
+    pub light_emission: f32,
+}
+
+impl TileSetDefinition {
+    pub fn tile_count(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    pub fn properties(&self, tile_index: usize) -> &TileProperties {
+        self.tile_properties
+            .get(&tile_index)
+            .unwrap_or(&self.properties)
+    }
+}
+
+/// Resource with the definition of every tile set, by name.
+#[derive(Default)]
+pub struct TileSetDefinitions(HashMap<String, TileSetDefinition>);
+
+impl TileSetDefinitions {
+    pub fn get(&self, tile_set: &str) -> Option<&TileSetDefinition> {
+        self.0.get(tile_set)
+    }
+
+    /// Properties of a tile, or `None` if its tile set isn't defined. Tiles
+    /// have the properties of their material, whichever tile they are drawn with.
+    pub fn properties(&self, tile: &Tile) -> Option<&TileProperties> {
+        self.get(&tile.tile_set)
+            .map(|definition| definition.properties(tile.material))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &TileSetDefinition)> {
+        self.0
+            .iter()
+            .map(|(name, definition)| (name.as_str(), definition))
+    }
+}
+
+/// Reads the manifest of every tile set that has a directory in
+/// `assets/tile_sets`. Tile sets with broken manifests are left out.
+pub fn load_tile_set_definitions() -> std::io::Result<TileSetDefinitions> {
+    let mut definitions = HashMap::default();
+
+    for entry in std::fs::read_dir(TILE_SETS_DIR)? {
+        let entry = entry?;
+
+        let name = match (entry.path().is_dir(), entry.file_name().to_str()) {
+            (true, Some(name)) => name.to_owned(),
+            _ => continue,
+        };
 
+        let manifest_path = entry.path().join(format!("{}.json", name));
+        let definition = std::fs::read_to_string(manifest_path)
+            .map_err(|e| e.to_string())
+            .and_then(|manifest| serde_json::from_str(&manifest).map_err(|e| e.to_string()));
+
+        match definition {
+            Ok(definition) => {
+                definitions.insert(name, definition);
+            }
+            Err(e) => eprintln!("Error loading tile set definition for {}: {}", name, e),
+        }
+    }
+
+    Ok(TileSetDefinitions(definitions))
+}
+
+/// Layers of the `TileMap`. Every tile position can hold one tile in each layer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TileLayer {
+    /// Blocks in the foreground, which the player and items collide with.
+    Block,
+    /// Walls behind the blocks, which are only a backdrop.
+    Wall,
+}
+
+impl TileLayer {
+    /// Every layer, from front to back.
+    pub const ALL: [TileLayer; 2] = [TileLayer::Block, TileLayer::Wall];
+}
+
+/// Places a tile in a layer, replacing the tile already at `tile_pos` there if there is one.
 pub struct SpawnBlockEvent {
+    pub layer: TileLayer,
+    pub tile_set: String,
+    pub tile_index: usize,
+    /// See `Tile::material`.
+    pub material: usize,
+    pub tile_pos: IVec2,
+}
+
+/// Removes the tile at `tile_pos` in a layer, if there is one.
+pub struct DespawnBlockEvent {
+    pub layer: TileLayer,
+    pub tile_pos: IVec2,
+}
+
+/// Sent after a tile has been placed, replaced or removed.
+pub struct TileChangedEvent {
+    pub layer: TileLayer,
+    pub tile_pos: IVec2,
+}
+
+/// Label of the system that spawns blocks from `SpawnBlockEvent`s. Systems
+/// that need the blocks of a chunk to be in the `TileMap` as soon as it's
+/// loaded run after it.
+#[derive(SystemLabel, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BlockSpawnLabel;
+
+/// Every block and wall, by layer and tile position. Systems add and remove
+/// tiles with `SpawnBlockEvent` and `DespawnBlockEvent`, which wrap `set` and
+/// `remove` and let the rest of the game know about the change. Tiles aren't
+/// entities, they are drawn and collided with per chunk.
+#[derive(Default)]
+pub struct TileMap {
+    blocks: HashMap<IVec2, Tile>,
+    walls: HashMap<IVec2, Tile>,
+}
+
+/// A block or wall in the `TileMap`.
+pub struct Tile {
     pub tile_set: String,
+    /// Tile of the tile set that the block or wall is drawn with.
     pub tile_index: usize,
-    pub tile_pos: Vec2,
+    /// Tile of the tile set that the block or wall was placed as, which
+    /// decides its properties. Auto-tiling only changes `tile_index`, so
+    /// that a stone block stays stone even when drawn with an edge tile.
+    pub material: usize,
+}
+
+impl TileMap {
+    pub fn get(&self, layer: TileLayer, tile_pos: IVec2) -> Option<&Tile> {
+        self.layer(layer).get(&tile_pos)
+    }
+
+    pub fn contains(&self, layer: TileLayer, tile_pos: IVec2) -> bool {
+        self.layer(layer).contains_key(&tile_pos)
+    }
+
+    pub fn iter(&self, layer: TileLayer) -> impl Iterator<Item = (IVec2, &Tile)> {
+        self.layer(layer)
+            .iter()
+            .map(|(tile_pos, tile)| (*tile_pos, tile))
+    }
+
+    /// Number of tiles in all layers.
+    pub fn len(&self) -> usize {
+        self.blocks.len() + self.walls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty() && self.walls.is_empty()
+    }
+
+    fn layer(&self, layer: TileLayer) -> &HashMap<IVec2, Tile> {
+        match layer {
+            TileLayer::Block => &self.blocks,
+            TileLayer::Wall => &self.walls,
+        }
+    }
+
+    fn layer_mut(&mut self, layer: TileLayer) -> &mut HashMap<IVec2, Tile> {
+        match layer {
+            TileLayer::Block => &mut self.blocks,
+            TileLayer::Wall => &mut self.walls,
+        }
+    }
+
+    /// Places a tile, returning the tile it replaced. Unlike `SpawnBlockEvent`,
+    /// this doesn't send a `TileChangedEvent`, so chunk meshes, colliders,
+    /// light and saves won't notice the change. Meant for filling a `TileMap`
+    /// outside of the game's systems, like in tools and tests.
+    pub fn set(&mut self, layer: TileLayer, tile_pos: IVec2, tile: Tile) -> Option<Tile> {
+        self.layer_mut(layer).insert(tile_pos, tile)
+    }
+
+    /// Removes a tile, without sending a `TileChangedEvent` like
+    /// `DespawnBlockEvent` does. See `set`.
+    pub fn remove(&mut self, layer: TileLayer, tile_pos: IVec2) -> Option<Tile> {
+        self.layer_mut(layer).remove(&tile_pos)
+    }
 }
 
 pub struct TileMapPlugin;
@@ -19,64 +260,89 @@ pub struct TileMapPlugin;
 impl Plugin for TileMapPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SpawnBlockEvent>()
+            .add_event::<DespawnBlockEvent>()
+            .add_event::<TileChangedEvent>()
+            .init_resource::<TileMap>()
             .add_startup_system(tile_map_setup_system)
-            .add_system_set(SystemSet::on_update(GameState::Game).with_system(block_spawn_system));
+            .add_system_set(
+                SystemSet::on_update(GameState::Game)
+                    .with_system(block_spawn_system.label(BlockSpawnLabel))
+                    .with_system(block_despawn_system),
+            );
     }
 }
 
 fn tile_map_setup_system(
     mut commands: Commands,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
     asset_server: Res<AssetServer>,
 ) {
-    // Jungle floor texture atlas
-    let jungle_floor_texture = asset_server.load(JUNGLE_FLOOR_SHEET);
-    let jungle_floor_atlas = TextureAtlas::from_grid(
-        jungle_floor_texture,
-        Vec2::new(BLOCK_SIZE, BLOCK_SIZE),
-        5,
-        5,
-    );
-    let jungle_floor = texture_atlases.add(jungle_floor_atlas);
-
+    let definitions = load_tile_set_definitions().unwrap_or_else(|e| {
+        eprintln!("Error loading tile set definitions: {}", e);
+        TileSetDefinitions::default()
+    });
     let mut tile_sets = TileSets::new();
-    tile_sets.insert("jungle_floor".to_owned(), jungle_floor);
+
+    for (name, definition) in definitions.iter() {
+        let sheet_path = format!("tile_sets/{}/{}", name, definition.sheet);
+        let texture: Handle<Image> = asset_server.load(&sheet_path);
+        let atlas = TextureAtlas::from_grid(
+            texture.clone(),
+            Vec2::splat(definition.tile_size),
+            definition.columns,
+            definition.rows,
+        );
+
+        tile_sets.insert(
+            name.to_owned(),
+            TileSetAssets {
+                atlas: texture_atlases.add(atlas),
+                materials: lit_materials(&mut materials, &texture, Color::WHITE),
+                wall_materials: lit_materials(&mut materials, &texture, WALL_TINT),
+            },
+        );
+    }
+
     commands.insert_resource(tile_sets);
+    commands.insert_resource(definitions);
+}
+
+/// Materials drawing a texture multiplied with `color`, for every light level.
+fn lit_materials(
+    materials: &mut Assets<ColorMaterial>,
+    texture: &Handle<Image>,
+    color: Color,
+) -> Vec<Handle<ColorMaterial>> {
+    (0..=MAX_LIGHT)
+        .map(|light_level| {
+            materials.add(ColorMaterial {
+                color: color * lighting::brightness(light_level),
+                texture: Some(texture.clone()),
+            })
+        })
+        .collect()
 }
 
 fn block_spawn_system(
-    mut commands: Commands,
-    tile_sets: Res<TileSets>,
+    definitions: Res<TileSetDefinitions>,
+    mut tile_map: ResMut<TileMap>,
     mut events: EventReader<SpawnBlockEvent>,
+    mut changed_events: EventWriter<TileChangedEvent>,
 ) {
     for spawn_data in events.iter() {
-        if let Some(atlas_handle) = tile_sets.get(&spawn_data.tile_set) {
-            let translation = Vec3::new(spawn_data.tile_pos.x, spawn_data.tile_pos.y, 0.0)
-                * SPRITE_SCALE
-                * BLOCK_SIZE;
-            let mut new_handle = Handle::<TextureAtlas>::default();
-            new_handle.id = atlas_handle.id;
-
-            commands
-                .spawn_bundle(SpriteSheetBundle {
-                    texture_atlas: new_handle,
-                    transform: Transform {
-                        translation,
-                        scale: Vec3::new(SPRITE_SCALE, SPRITE_SCALE, SPRITE_SCALE),
-                        ..Default::default()
-                    },
-                    sprite: TextureAtlasSprite {
-                        index: spawn_data.tile_index,
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .insert(Block {
-                    tile_set: spawn_data.tile_set.clone(),
-                    tile_index: spawn_data.tile_index,
-                })
-                .insert(RigidBody::Fixed)
-                .insert(Collider::cuboid(BLOCK_SIZE / 2., BLOCK_SIZE / 2.));
+        if definitions.get(&spawn_data.tile_set).is_some() {
+            let tile = Tile {
+                tile_set: spawn_data.tile_set.clone(),
+                tile_index: spawn_data.tile_index,
+                material: spawn_data.material,
+            };
+            tile_map.set(spawn_data.layer, spawn_data.tile_pos, tile);
+
+            changed_events.send(TileChangedEvent {
+                layer: spawn_data.layer,
+                tile_pos: spawn_data.tile_pos,
+            });
         } else {
             eprintln!(
                 "Tried to spawn block belonging to undefined tile set: {}",
@@ -85,3 +351,21 @@ fn block_spawn_system(
         }
     }
 }
+
+fn block_despawn_system(
+    mut tile_map: ResMut<TileMap>,
+    mut events: EventReader<DespawnBlockEvent>,
+    mut changed_events: EventWriter<TileChangedEvent>,
+) {
+    for despawn_data in events.iter() {
+        if tile_map
+            .remove(despawn_data.layer, despawn_data.tile_pos)
+            .is_some()
+        {
+            changed_events.send(TileChangedEvent {
+                layer: despawn_data.layer,
+                tile_pos: despawn_data.tile_pos,
+            });
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Time of day in the loaded world. The clock runs while the game is being
+//! played, is saved with the world, and drives the colour of the sky and the
+//! strength of sunlight.
+
+use bevy::prelude::*;
+
+use crate::GameState;
+
+/// Length of a full day and night, in seconds.
+pub const DAY_LENGTH: f64 = 1200.;
+
+/// Time new worlds start at, shortly after sunrise.
+pub const NEW_WORLD_TIME: f64 = DAY_LENGTH * 0.3;
+
+/// Colour of the sky through the day, as times of day and the sky's colour
+/// at that time. The sky blends between the colours in between.
+const SKY_COLORS: [(f32, [f32; 3]); 8] = [
+    // Night
+    (0.0, [0.04, 0.06, 0.15]),
+    (0.2, [0.04, 0.06, 0.15]),
+    // Dawn
+    (0.25, [0.96, 0.64, 0.38]),
+    // Day
+    (0.3, [0.53, 0.81, 0.92]),
+    (0.7, [0.53, 0.81, 0.92]),
+    // Dusk
+    (0.75, [0.99, 0.37, 0.33]),
+    // Night
+    (0.8, [0.04, 0.06, 0.15]),
+    (1.0, [0.04, 0.06, 0.15]),
+];
+
+/// Strength of sunlight through the day, like `SKY_COLORS`.
+const DAYLIGHT: [(f32, f32); 6] = [
+    (0.0, 0.),
+    (0.2, 0.),
+    (0.3, 1.),
+    (0.7, 1.),
+    (0.8, 0.),
+    (1.0, 0.),
+];
+
+/// Resource with the time that has passed in the loaded world.
+pub struct WorldClock {
+    /// Seconds since midnight of the world's first day.
+    elapsed: f64,
+}
+
+impl WorldClock {
+    pub fn new(elapsed: f64) -> Self {
+        Self { elapsed }
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Number of the current day, starting at 0.
+    pub fn day(&self) -> u64 {
+        (self.elapsed / DAY_LENGTH) as u64
+    }
+
+    /// How far through the current day the clock is, from 0 at
+    /// midnight through 0.5 at noon to 1 at the next midnight.
+    pub fn time_of_day(&self) -> f32 {
+        (self.elapsed.rem_euclid(DAY_LENGTH) / DAY_LENGTH) as f32
+    }
+
+    /// Strength of sunlight, from 0 at night to 1 during the day.
+    pub fn daylight(&self) -> f32 {
+        let (from, to, blend) = keyframes_around(&DAYLIGHT, self.time_of_day());
+        from + (to - from) * blend
+    }
+
+    /// Whether the sun is down completely.
+    pub fn is_night(&self) -> bool {
+        self.daylight() <= 0.
+    }
+
+    pub fn sky_color(&self) -> Color {
+        let (from, to, blend) = keyframes_around(&SKY_COLORS, self.time_of_day());
+        let [red, green, blue] = [0, 1, 2].map(|i| from[i] + (to[i] - from[i]) * blend);
+        Color::rgb(red, green, blue)
+    }
+}
+
+/// Values of the keyframes right before and after a time of day, and how
+/// far the time is from the first keyframe to the second, from 0 to 1.
+/// The keyframes have to be sorted by time and cover the whole day.
+fn keyframes_around<T: Copy>(keyframes: &[(f32, T)], time_of_day: f32) -> (T, T, f32) {
+    let next = keyframes
+        .iter()
+        .position(|(time, _)| *time > time_of_day)
+        .unwrap_or(keyframes.len() - 1)
+        .max(1);
+    let (from_time, from) = keyframes[next - 1];
+    let (to_time, to) = keyframes[next];
+
+    let blend = ((time_of_day - from_time) / (to_time - from_time)).clamp(0., 1.);
+    (from, to, blend)
+}
+
+pub struct WorldClockPlugin;
+
+impl Plugin for WorldClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_update(GameState::Game).with_system(world_clock_system));
+    }
+}
+
+/// System that advances the clock and colours the sky to match the time of day.
+fn world_clock_system(
+    time: Res<Time>,
+    world_clock: Option<ResMut<WorldClock>>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    // No world has been loaded yet
+    let mut world_clock = match world_clock {
+        Some(world_clock) => world_clock,
+        None => return,
+    };
+
+    world_clock.elapsed += time.delta_seconds_f64();
+    clear_color.0 = world_clock.sky_color();
+}
@@ -0,0 +1,457 @@
+//! Seeded terrain generation for new worlds: hills of dirt over stone, with
+//! caves of water and lava, veins of obsidian deep down and mounds on the
+//! surface. Everything here is a pure function of the seed and the tile
+//! position, so a seed always produces the same world and any part of it
+//! can be generated on its own.
+
+use std::ops::Range;
+
+use bevy::prelude::*;
+
+use crate::{
+    auto_tile::{self, AutoTileRules},
+    coords,
+    liquids::{LiquidKind, OBSIDIAN_TILE_SET},
+    tile_map::TileLayer,
+};
+
+/// Tile set that generated terrain is made of.
+pub const TERRAIN_TILE_SET: &str = "jungle_floor";
+
+/// Columns and rows of chunks that get generated.
+const WORLD_CHUNKS_X: Range<i32> = -8..8;
+pub const WORLD_CHUNKS_Y: Range<i32> = -6..2;
+
+/// Average height of the surface, in tiles.
+const SURFACE_LEVEL: f32 = 0.;
+/// Furthest the surface strays from `SURFACE_LEVEL`, in tiles.
+const SURFACE_AMPLITUDE: f32 = 14.;
+/// Width of the widest hills, in tiles.
+const SURFACE_SCALE: f32 = 48.;
+
+/// Dirt goes this many tiles below the surface, plus up to `DIRT_VARIATION` more.
+const DIRT_DEPTH: i32 = 4;
+const DIRT_VARIATION: f32 = 4.;
+
+/// Size of the largest caves, in tiles.
+const CAVE_SCALE: f32 = 20.;
+/// Noise value above which a tile is part of a cave. Caves are rarer
+/// than this within `CAVE_FADE_DEPTH` tiles of the surface.
+const CAVE_THRESHOLD: f32 = 0.66;
+const CAVE_FADE_DEPTH: f32 = 16.;
+
+/// Chance of a small mound on top of any surface tile.
+const MOUND_CHANCE: f32 = 0.06;
+
+/// Veins of obsidian run through the stone below this height.
+const OBSIDIAN_LEVEL: i32 = -40;
+/// Length and thickness of the largest veins of obsidian, in tiles.
+const OBSIDIAN_LENGTH: f32 = 12.;
+const OBSIDIAN_THICKNESS: f32 = 4.;
+/// Noise value above which a stone tile is part of a vein of obsidian.
+const OBSIDIAN_THRESHOLD: f32 = 0.77;
+
+/// Caves below this height are flooded with lava.
+const LAVA_LEVEL: i32 = -72;
+/// Size of the largest pockets of water in caves, in tiles.
+const WATER_SCALE: f32 = 10.;
+/// Noise value above which a cave tile starts out filled with water.
+const WATER_THRESHOLD: f32 = 0.62;
+
+// Fill tiles of the jungle floor sheet, used for blocks with no open sides
+const DIRT: usize = 7;
+const STONE: usize = 12;
+
+// Seeds of the separate noise layers, mixed into the world seed
+const SURFACE_LAYER: u64 = 1;
+const DIRT_LAYER: u64 = 2;
+const CAVE_LAYER: u64 = 3;
+const MOUND_LAYER: u64 = 4;
+const WATER_LAYER: u64 = 5;
+const OBSIDIAN_LAYER: u64 = 6;
+
+#[derive(Clone, Copy)]
+enum Material {
+    Dirt,
+    Stone,
+    Obsidian,
+}
+
+/// A generated block or wall.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GeneratedTile {
+    pub layer: TileLayer,
+    pub tile_pos: IVec2,
+    pub tile_set: &'static str,
+    pub tile_index: usize,
+    /// See `Tile::material`.
+    pub material: usize,
+}
+
+pub struct WorldGenerator {
+    seed: u64,
+    /// Auto-tiling rules of `TERRAIN_TILE_SET`, which give the terrain its edges.
+    terrain_rules: AutoTileRules,
+}
+
+impl WorldGenerator {
+    pub fn new(seed: u64, terrain_rules: AutoTileRules) -> Self {
+        Self {
+            seed,
+            terrain_rules,
+        }
+    }
+
+    /// Every generated block and wall in the world.
+    pub fn generate(&self) -> Vec<GeneratedTile> {
+        let mut tiles = Vec::new();
+
+        for chunk_y in WORLD_CHUNKS_Y {
+            for chunk_x in WORLD_CHUNKS_X {
+                tiles.extend(self.generate_chunk(IVec2::new(chunk_x, chunk_y)));
+            }
+        }
+
+        tiles
+    }
+
+    /// Generated blocks and walls of a single chunk.
+    fn generate_chunk(&self, chunk_pos: IVec2) -> Vec<GeneratedTile> {
+        coords::chunk_tiles(chunk_pos)
+            .flat_map(|tile_pos| {
+                TileLayer::ALL
+                    .into_iter()
+                    .filter_map(move |layer| self.tile(layer, tile_pos))
+            })
+            .collect()
+    }
+
+    /// Every tile that starts out full of liquid. Liquids are only placed in
+    /// caves, where they flow down and settle once their chunk is loaded.
+    pub fn generate_liquids(&self) -> Vec<(IVec2, LiquidKind)> {
+        let mut liquids = Vec::new();
+
+        for chunk_y in WORLD_CHUNKS_Y {
+            for chunk_x in WORLD_CHUNKS_X {
+                for tile_pos in coords::chunk_tiles(IVec2::new(chunk_x, chunk_y)) {
+                    if let Some(kind) = self.liquid(tile_pos) {
+                        liquids.push((tile_pos, kind));
+                    }
+                }
+            }
+        }
+
+        liquids
+    }
+
+    /// Tile position above the surface in the middle of the world,
+    /// where new players appear.
+    pub fn spawn_point(&self) -> IVec2 {
+        IVec2::new(0, self.surface_height(0) + 3)
+    }
+
+    /// Height of the highest dirt tile in a column, not counting mounds.
+    fn surface_height(&self, x: i32) -> i32 {
+        let noise = fractal_noise_1d(self.layer_seed(SURFACE_LAYER), x as f32 / SURFACE_SCALE, 4);
+        (SURFACE_LEVEL + (noise * 2. - 1.) * SURFACE_AMPLITUDE).round() as i32
+    }
+
+    /// Material of the block at a tile, if there is one.
+    fn material(&self, tile_pos: IVec2) -> Option<Material> {
+        if !in_world(tile_pos) {
+            return None;
+        }
+
+        let surface_height = self.surface_height(tile_pos.x);
+        let depth = surface_height - tile_pos.y;
+
+        if depth < 0 {
+            // Mounds only grow on flat ground, so that they don't float off slopes
+            let is_mound = depth == -1
+                && self.surface_height(tile_pos.x - 1) == surface_height
+                && self.surface_height(tile_pos.x + 1) == surface_height
+                && random(self.layer_seed(MOUND_LAYER), tile_pos.x, 0) < MOUND_CHANCE;
+
+            return if is_mound { Some(Material::Dirt) } else { None };
+        }
+
+        let cave_noise = fractal_noise_2d(
+            self.layer_seed(CAVE_LAYER),
+            tile_pos.as_vec2() / CAVE_SCALE,
+            3,
+        );
+        let surface_fade = (1. - depth as f32 / CAVE_FADE_DEPTH).max(0.);
+        if cave_noise > CAVE_THRESHOLD + surface_fade * (1. - CAVE_THRESHOLD) {
+            return None;
+        }
+
+        match self.ground_material(tile_pos, depth) {
+            Material::Stone if self.in_obsidian_vein(tile_pos) => Some(Material::Obsidian),
+            material => Some(material),
+        }
+    }
+
+    /// Whether a tile deep enough for obsidian is part of one of its veins.
+    fn in_obsidian_vein(&self, tile_pos: IVec2) -> bool {
+        if tile_pos.y >= OBSIDIAN_LEVEL {
+            return false;
+        }
+
+        let vein_noise = fractal_noise_2d(
+            self.layer_seed(OBSIDIAN_LAYER),
+            tile_pos.as_vec2() / Vec2::new(OBSIDIAN_LENGTH, OBSIDIAN_THICKNESS),
+            2,
+        );
+        vein_noise > OBSIDIAN_THRESHOLD
+    }
+
+    /// Material of the wall at a tile, if there is one. Walls fill everything
+    /// below the surface, caves included, but stop a tile short of it so that
+    /// they don't show around the edges of the terrain.
+    fn wall_material(&self, tile_pos: IVec2) -> Option<Material> {
+        if !in_world(tile_pos) {
+            return None;
+        }
+
+        let depth = self.surface_height(tile_pos.x) - tile_pos.y;
+        if depth < 1 {
+            return None;
+        }
+
+        Some(self.ground_material(tile_pos, depth))
+    }
+
+    /// Liquid that a tile starts out full of, if any.
+    fn liquid(&self, tile_pos: IVec2) -> Option<LiquidKind> {
+        let in_cave = in_world(tile_pos)
+            && tile_pos.y <= self.surface_height(tile_pos.x)
+            && self.material(tile_pos).is_none();
+        if !in_cave {
+            return None;
+        }
+
+        if tile_pos.y < LAVA_LEVEL {
+            return Some(LiquidKind::Lava);
+        }
+
+        let water_noise = fractal_noise_2d(
+            self.layer_seed(WATER_LAYER),
+            tile_pos.as_vec2() / WATER_SCALE,
+            2,
+        );
+        if water_noise > WATER_THRESHOLD {
+            Some(LiquidKind::Water)
+        } else {
+            None
+        }
+    }
+
+    /// Material of the ground `depth` tiles below the surface.
+    fn ground_material(&self, tile_pos: IVec2, depth: i32) -> Material {
+        let dirt_depth = DIRT_DEPTH
+            + (random(self.layer_seed(DIRT_LAYER), tile_pos.x, 0) * DIRT_VARIATION) as i32;
+        if depth <= dirt_depth {
+            Material::Dirt
+        } else {
+            Material::Stone
+        }
+    }
+
+    /// Picks the tile of the jungle floor sheet that matches the material of
+    /// the tile in a layer and which of its sides are exposed to air. Obsidian
+    /// has a tile set of its own, with a single tile.
+    fn tile(&self, layer: TileLayer, tile_pos: IVec2) -> Option<GeneratedTile> {
+        let material = |tile_pos| match layer {
+            TileLayer::Block => self.material(tile_pos),
+            TileLayer::Wall => self.wall_material(tile_pos),
+        };
+
+        let fill_tile = match material(tile_pos)? {
+            Material::Dirt => DIRT,
+            Material::Stone => STONE,
+            Material::Obsidian => {
+                return Some(GeneratedTile {
+                    layer,
+                    tile_pos,
+                    tile_set: OBSIDIAN_TILE_SET,
+                    tile_index: 0,
+                    material: 0,
+                })
+            }
+        };
+        let neighbours = auto_tile::neighbour_mask(|offset| material(tile_pos + offset).is_some());
+
+        Some(GeneratedTile {
+            layer,
+            tile_pos,
+            tile_set: TERRAIN_TILE_SET,
+            tile_index: self.terrain_rules.pick(neighbours, fill_tile),
+            material: fill_tile,
+        })
+    }
+
+    fn layer_seed(&self, layer: u64) -> u64 {
+        splitmix64(self.seed ^ splitmix64(layer))
+    }
+}
+
+/// Whether a tile is in one of the chunks that get generated.
+fn in_world(tile_pos: IVec2) -> bool {
+    let chunk_pos = coords::tile_to_chunk(tile_pos);
+    WORLD_CHUNKS_X.contains(&chunk_pos.x) && WORLD_CHUNKS_Y.contains(&chunk_pos.y)
+}
+
+/// Picks a seed for a new world from the `--seed <seed>` command line
+/// argument, or from the current time if there isn't one.
+pub fn seed_from_args() -> u64 {
+    let seed_arg = std::env::args()
+        .skip_while(|arg| arg != "--seed")
+        .nth(1)
+        .and_then(|seed| seed.parse().ok());
+
+    seed_arg.unwrap_or_else(|| {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+        splitmix64(nanos)
+    })
+}
+
+/// A well mixed 64 bit hash, see https://prng.di.unimi.it/splitmix64.c
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Random number between 0 and 1 for a point on an integer grid.
+fn random(seed: u64, x: i32, y: i32) -> f32 {
+    let hash = splitmix64(seed ^ splitmix64(((x as u32 as u64) << 32) | y as u32 as u64));
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3. - 2. * t)
+}
+
+fn value_noise_1d(seed: u64, x: f32) -> f32 {
+    let x0 = x.floor();
+    let t = smoothstep(x - x0);
+
+    let a = random(seed, x0 as i32, 0);
+    let b = random(seed, x0 as i32 + 1, 0);
+    a + (b - a) * t
+}
+
+fn value_noise_2d(seed: u64, point: Vec2) -> f32 {
+    let corner = point.floor();
+    let t = point - corner;
+    let (tx, ty) = (smoothstep(t.x), smoothstep(t.y));
+    let (x0, y0) = (corner.x as i32, corner.y as i32);
+
+    let bottom = random(seed, x0, y0) + (random(seed, x0 + 1, y0) - random(seed, x0, y0)) * tx;
+    let top =
+        random(seed, x0, y0 + 1) + (random(seed, x0 + 1, y0 + 1) - random(seed, x0, y0 + 1)) * tx;
+    bottom + (top - bottom) * ty
+}
+
+/// Sum of `octaves` layers of value noise, each with double the frequency and
+/// half the amplitude of the previous one. Stays between 0 and 1.
+fn fractal_noise_1d(seed: u64, x: f32, octaves: u32) -> f32 {
+    let mut total = 0.;
+    let mut amplitude = 1.;
+    let mut amplitude_sum = 0.;
+
+    for octave in 0..octaves {
+        let frequency = (1 << octave) as f32;
+        total +=
+            value_noise_1d(splitmix64(seed.wrapping_add(octave as u64)), x * frequency) * amplitude;
+        amplitude_sum += amplitude;
+        amplitude /= 2.;
+    }
+
+    total / amplitude_sum
+}
+
+fn fractal_noise_2d(seed: u64, point: Vec2, octaves: u32) -> f32 {
+    let mut total = 0.;
+    let mut amplitude = 1.;
+    let mut amplitude_sum = 0.;
+
+    for octave in 0..octaves {
+        let frequency = (1 << octave) as f32;
+        total += value_noise_2d(
+            splitmix64(seed.wrapping_add(octave as u64)),
+            point * frequency,
+        ) * amplitude;
+        amplitude_sum += amplitude;
+        amplitude /= 2.;
+    }
+
+    total / amplitude_sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile_map;
+
+    fn generator(seed: u64) -> WorldGenerator {
+        let terrain_rules = tile_map::load_tile_set_definitions()
+            .unwrap()
+            .get(TERRAIN_TILE_SET)
+            .and_then(|definition| definition.auto_tile.clone())
+            .unwrap();
+
+        WorldGenerator::new(seed, terrain_rules)
+    }
+
+    #[test]
+    fn same_seed_generates_same_world() {
+        let (first, second) = (generator(42), generator(42));
+
+        assert_eq!(first.generate(), second.generate());
+        assert_eq!(first.generate_liquids(), second.generate_liquids());
+        assert_eq!(first.spawn_point(), second.spawn_point());
+    }
+
+    #[test]
+    fn different_seeds_generate_different_worlds() {
+        let (first, second) = (generator(42), generator(43));
+
+        assert_ne!(first.generate(), second.generate());
+        assert_ne!(first.generate_liquids(), second.generate_liquids());
+    }
+
+    #[test]
+    fn chunks_generate_the_same_on_their_own() {
+        let generator = generator(42);
+        let world = generator.generate();
+
+        for chunk_pos in [IVec2::new(-1, -1), IVec2::new(0, 0), IVec2::new(3, -5)] {
+            let chunk_tiles: Vec<GeneratedTile> = world
+                .iter()
+                .filter(|generated| coords::tile_to_chunk(generated.tile_pos) == chunk_pos)
+                .cloned()
+                .collect();
+
+            assert_eq!(generator.generate_chunk(chunk_pos), chunk_tiles);
+        }
+    }
+
+    #[test]
+    fn obsidian_veins_run_through_deep_stone() {
+        let veins: Vec<GeneratedTile> = generator(42)
+            .generate()
+            .into_iter()
+            .filter(|generated| generated.tile_set == OBSIDIAN_TILE_SET)
+            .collect();
+
+        assert!(!veins.is_empty());
+        assert!(veins
+            .iter()
+            .all(|vein| { vein.layer == TileLayer::Block && vein.tile_pos.y < OBSIDIAN_LEVEL }));
+    }
+}